@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use kle_serial::{KeyIterator, Keyboard};
+
+// KLE's ANSI 104 preset
+const ANSI_104: &str = r##"[
+    {"name": "ANSI 104", "author": "KLE", "backcolor": "#eeeeee"},
+    ["Esc", {"x": 1}, "F1", "F2", "F3", "F4", {"x": 0.5}, "F5", "F6", "F7", "F8", {"x": 0.5}, "F9", "F10", "F11", "F12", {"x": 0.25}, "PrtSc", "Scroll Lock", "Pause\nBreak"],
+    [{"y": 0.5}, "~\n`", "!\n1", "@\n2", "#\n3", "$\n4", "%\n5", "^\n6", "&\n7", "*\n8", "(\n9", ")\n0", "_\n-", "+\n=", {"w": 2}, "Backspace", {"x": 0.25}, "Insert", "Home", "PgUp", {"x": 0.25}, "Num Lock", "/", "*", "-"],
+    [{"w": 1.5}, "Tab", "Q", "W", "E", "R", "T", "Y", "U", "I", "O", "P", "{\n[", "}\n]", {"w": 1.5}, "|\n\\", {"x": 0.25}, "Delete", "End", "PgDn", {"x": 0.25}, "7\nHome", "8\n↑", "9\nPgUp", {"h": 2}, "+"],
+    [{"w": 1.75}, "Caps Lock", "A", "S", "D", {"n": true}, "F", {"n": false}, "G", "H", {"n": true}, "J", {"n": false}, "K", "L", ":\n;", "\"\n'", {"w": 2.25}, "Enter", {"x": 3.5}, "4\n←", {"n": true}, "5", {"n": false}, "6\n→"],
+    [{"w": 2.25}, "Shift", "Z", "X", "C", "V", "B", "N", "M", "<\n,", ">\n.", "?\n/", {"w": 2.75}, "Shift", {"x": 1.25}, "↑", {"x": 1.25}, "1\nEnd", "2\n↓", "3\nPgDn", {"h": 2}, "Enter"],
+    [{"w": 1.25}, "Ctrl", {"w": 1.25}, "Win", {"w": 1.25}, "Alt", {"a": 7, "w": 6.25}, "", {"a": 4, "w": 1.25}, "Alt", {"w": 1.25}, "Win", {"w": 1.25}, "Menu", {"w": 1.25}, "Ctrl", {"x": 0.25}, "←", "↓", "→", {"x": 0.25, "w": 2}, "0\nIns", ".\nDel"]
+]"##;
+
+// A large synthetic layout using many different properties
+fn large_layout() -> String {
+    let rows: Vec<_> = (0..200)
+        .map(|row| {
+            let keys: Vec<_> = (0..25)
+                .map(|col| {
+                    format!(
+                        r##"{{"c": "#{:02x}{:02x}{:02x}", "t": "#000000\n\n#ff0000", "f": {}, "w": 1.25}}, "A{row}\nB{col}\n\n\nC\nD\n\n\n\nE""##,
+                        row % 256,
+                        col * 10,
+                        (row + col) % 256,
+                        col % 9 + 1,
+                    )
+                })
+                .collect();
+            let rotation = if row % 10 == 0 {
+                format!(r#"{{"r": {}, "rx": 1, "ry": {row}}}, "#, row % 45)
+            } else {
+                String::new()
+            };
+            format!("[{rotation}{}]", keys.join(", "))
+        })
+        .collect();
+
+    format!(r#"[{{"name": "large"}}, {}]"#, rows.join(", "))
+}
+
+fn bench_deserialize(c: &mut Criterion) {
+    let large = large_layout();
+
+    let mut group = c.benchmark_group("deserialize");
+    for (name, json) in [("ansi_104", ANSI_104), ("large", large.as_str())] {
+        let num_keys = serde_json::from_str::<Keyboard>(json).unwrap().keys.len();
+        group.throughput(Throughput::Elements(num_keys as u64));
+
+        group.bench_function(format!("{name}/keyboard"), |b| {
+            b.iter(|| serde_json::from_str::<Keyboard>(black_box(json)).unwrap());
+        });
+        group.bench_function(format!("{name}/key_iterator"), |b| {
+            b.iter(|| {
+                serde_json::from_str::<KeyIterator>(black_box(json))
+                    .unwrap()
+                    .for_each(drop);
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_deserialize);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use kle_serial::{AnalyzedKeyboard, Key, Keyboard, Rect};
+
+// A synthetic layout with a rotation cluster every few rows
+fn layout() -> Keyboard {
+    let rows: Vec<_> = (0..40)
+        .map(|row| {
+            let keys = vec![r#""A""#; 20].join(", ");
+            if row % 4 == 0 {
+                format!(r#"[{{"r": {}, "rx": 1, "ry": {row}}}, {keys}]"#, row % 45)
+            } else {
+                format!("[{keys}]")
+            }
+        })
+        .collect();
+
+    serde_json::from_str(&format!("[{}]", rows.join(", "))).unwrap()
+}
+
+// Simulates an application querying the layout's geometry once per frame
+fn bench_repeated_queries(c: &mut Criterion) {
+    let keyboard = layout();
+    let analyzed = AnalyzedKeyboard::new(keyboard.clone());
+
+    let mut group = c.benchmark_group("repeated_queries");
+    group.bench_function("keyboard", |b| {
+        b.iter(|| {
+            let keyboard = black_box(&keyboard);
+            let footprints: Vec<Rect<f64>> =
+                keyboard.keys.iter().map(Key::rotated_bounds).collect();
+            (
+                keyboard.bounding_box(),
+                footprints.len(),
+                keyboard.rotation_clusters().len(),
+            )
+        });
+    });
+    group.bench_function("analyzed_keyboard", |b| {
+        b.iter(|| {
+            let analyzed = black_box(&analyzed);
+            (
+                analyzed.bounding_box(),
+                analyzed.footprints().len(),
+                analyzed.rotation_clusters().len(),
+            )
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_repeated_queries);
+criterion_main!(benches);
@@ -0,0 +1,152 @@
+use num_traits::real::Real;
+
+use crate::{Keyboard, Rect, RotationParams};
+
+/// A [`Keyboard`] along with cached geometry, for applications that repeatedly query a layout.
+///
+/// The layout's [`bounding box`](Keyboard::bounding_box), each key's
+/// [`rotated bounds`](crate::Key::rotated_bounds) (its footprint), and the layout's
+/// [`rotation clusters`](Keyboard::rotation_clusters) are calculated once when the wrapper is
+/// created, so querying them is effectively free.
+///
+/// To keep the cache consistent, the keyboard can only be mutated through
+/// [`AnalyzedKeyboard::modify`], which recalculates everything after the given closure returns.
+/// It is therefore best to batch changes into a single call rather than modifying one key at a
+/// time.
+///
+/// # Example
+///
+/// ```
+/// use kle_serial::{AnalyzedKeyboard, Keyboard};
+///
+/// let keyboard: Keyboard = serde_json::from_str(r#"[["A", "B"]]"#).unwrap();
+/// let mut analyzed = AnalyzedKeyboard::new(keyboard);
+/// assert_eq!(analyzed.bounding_box().unwrap().width, 2.0);
+///
+/// analyzed.modify(|keyboard| keyboard.keys[1].width = 2.0);
+/// assert_eq!(analyzed.bounding_box().unwrap().width, 3.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyzedKeyboard<T = f64>
+where
+    T: Real,
+{
+    keyboard: Keyboard<T>,
+    bounding_box: Option<Rect<T>>,
+    footprints: Vec<Rect<T>>,
+    rotation_clusters: Vec<(RotationParams<T>, Vec<usize>)>,
+}
+
+impl<T> AnalyzedKeyboard<T>
+where
+    T: Real,
+{
+    /// Wraps the given keyboard, calculating its geometry.
+    #[must_use]
+    pub fn new(keyboard: Keyboard<T>) -> Self {
+        let mut result = Self {
+            keyboard,
+            bounding_box: None,
+            footprints: Vec::new(),
+            rotation_clusters: Vec::new(),
+        };
+        result.update();
+        result
+    }
+
+    fn update(&mut self) {
+        self.bounding_box = self.keyboard.bounding_box();
+        self.footprints = (self.keyboard.keys.iter())
+            .map(crate::Key::rotated_bounds)
+            .collect();
+        self.rotation_clusters = self.keyboard.rotation_clusters();
+    }
+
+    /// Returns a reference to the wrapped keyboard.
+    #[must_use]
+    pub fn keyboard(&self) -> &Keyboard<T> {
+        &self.keyboard
+    }
+
+    /// Unwraps the keyboard, discarding the cached geometry.
+    #[must_use]
+    pub fn into_inner(self) -> Keyboard<T> {
+        self.keyboard
+    }
+
+    /// Mutates the wrapped keyboard using the given closure, returning the closure's result.
+    ///
+    /// All cached geometry is recalculated after the closure returns, even if the keyboard was not
+    /// changed.
+    pub fn modify<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut Keyboard<T>) -> R,
+    {
+        let result = f(&mut self.keyboard);
+        self.update();
+        result
+    }
+
+    /// Returns the cached result of [`Keyboard::bounding_box`].
+    #[must_use]
+    pub fn bounding_box(&self) -> Option<Rect<T>> {
+        self.bounding_box
+    }
+
+    /// Returns the cached result of [`Key::rotated_bounds`](crate::Key::rotated_bounds) for each
+    /// key, in the same order as [`Keyboard::keys`]. Unlike [`AnalyzedKeyboard::bounding_box`],
+    /// decals are included.
+    #[must_use]
+    pub fn footprints(&self) -> &[Rect<T>] {
+        &self.footprints
+    }
+
+    /// Returns the cached result of [`Keyboard::rotation_clusters`].
+    #[must_use]
+    pub fn rotation_clusters(&self) -> &[(RotationParams<T>, Vec<usize>)] {
+        &self.rotation_clusters
+    }
+}
+
+impl<T> From<Keyboard<T>> for AnalyzedKeyboard<T>
+where
+    T: Real,
+{
+    fn from(value: Keyboard<T>) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyzed_keyboard() {
+        let keyboard: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", "B"],
+                [{"r": 15, "rx": 1, "ry": 1}, "C"],
+                [{"d": true}, "Decal"]
+            ]"#,
+        )
+        .unwrap();
+        let mut analyzed = AnalyzedKeyboard::from(keyboard.clone());
+
+        assert_eq!(analyzed.keyboard(), &keyboard);
+        assert_eq!(analyzed.bounding_box(), keyboard.bounding_box());
+        assert_eq!(analyzed.rotation_clusters(), keyboard.rotation_clusters());
+        assert_eq!(analyzed.footprints().len(), 4);
+        assert_eq!(analyzed.footprints()[1], keyboard.keys[1].rotated_bounds());
+
+        // The decal inherits the rotation, so this removes the whole rotation cluster
+        let removed = analyzed.modify(|keyboard| keyboard.keys.split_off(2));
+        assert_eq!(removed.len(), 2);
+        assert_eq!(analyzed.footprints().len(), 2);
+        assert_eq!(analyzed.rotation_clusters().len(), 1);
+        assert_eq!(analyzed.bounding_box(), analyzed.keyboard().bounding_box());
+        assert_ne!(analyzed.bounding_box(), keyboard.bounding_box());
+
+        assert_eq!(analyzed.into_inner().keys.len(), 2);
+    }
+}
@@ -0,0 +1,272 @@
+use num_traits::real::Real;
+
+use crate::{Key, Keyboard, Metadata};
+
+/// A builder used to construct or edit a [`Keyboard`].
+///
+/// Keys can either be added fully specified using [`LayoutBuilder::key`], in which case they are
+/// added as is, or incrementally using [`LayoutBuilder::next_key`], in which case the key is
+/// positioned at the builder's cursor similar to how KLE positions keys. After adding a key the
+/// cursor is moved to the right of that key, and [`LayoutBuilder::new_row`] moves it to the start
+/// of the next row.
+///
+/// An existing keyboard can be edited by calling [`Keyboard::to_builder`].
+///
+/// # Example
+///
+/// ```
+/// use kle_serial::{Key, LayoutBuilder};
+///
+/// let keyboard = LayoutBuilder::<f64>::new()
+///     .next_key(Key::default())
+///     .next_key(Key { width: 2.0, ..Key::default() })
+///     .new_row()
+///     .next_key(Key::default())
+///     .build();
+///
+/// assert_eq!(keyboard.keys.len(), 3);
+/// assert_eq!(keyboard.keys[1].x, 1.0);
+/// assert_eq!(keyboard.keys[2].y, 1.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LayoutBuilder<T = f64>
+where
+    T: Real,
+{
+    metadata: Metadata,
+    keys: Vec<Key<T>>,
+    x: T,
+    y: T,
+}
+
+impl<T> Default for LayoutBuilder<T>
+where
+    T: Real,
+{
+    fn default() -> Self {
+        Self {
+            metadata: Metadata::default(),
+            keys: Vec::new(),
+            x: T::zero(),
+            y: T::zero(),
+        }
+    }
+}
+
+impl<T> LayoutBuilder<T>
+where
+    T: Real,
+{
+    /// Creates a new empty builder.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the keyboard's metadata.
+    #[must_use]
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Adds a fully specified key, keeping its position as is.
+    ///
+    /// The cursor is moved to the right of the added key. Like in KLE, this includes the key's
+    /// secondary rectangle (`x2` and `width2`) if it extends further to the right, as on a
+    /// stepped key.
+    #[must_use]
+    pub fn key(mut self, key: Key<T>) -> Self {
+        self.x = key.x + key.width.max(key.x2 + key.width2);
+        self.y = key.y;
+        self.keys.push(key);
+        self
+    }
+
+    /// Adds multiple fully specified keys. This is equivalent to calling [`LayoutBuilder::key`]
+    /// for each key.
+    #[must_use]
+    pub fn keys<I>(self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = Key<T>>,
+    {
+        keys.into_iter().fold(self, Self::key)
+    }
+
+    /// Adds a key at the builder's cursor, overwriting the key's position.
+    ///
+    /// The cursor is moved to the right of the added key.
+    #[must_use]
+    pub fn next_key(self, key: Key<T>) -> Self {
+        let (x, y) = (self.x, self.y);
+        self.key(Key { x, y, ..key })
+    }
+
+    /// Returns a mutable reference to the keys added so far, allowing them to be edited, removed,
+    /// or reordered.
+    ///
+    /// The cursor is not affected by any changes made to the keys.
+    pub fn keys_mut(&mut self) -> &mut Vec<Key<T>> {
+        &mut self.keys
+    }
+
+    /// Moves the cursor to the start of the next row.
+    ///
+    /// Like in KLE, rows start at the X coordinate of the last key's centre of rotation.
+    #[must_use]
+    pub fn new_row(mut self) -> Self {
+        self.x = self.keys.last().map_or_else(T::zero, |key| key.rx);
+        self.y = self.y + T::one();
+        self
+    }
+
+    /// Builds the keyboard.
+    #[must_use]
+    pub fn build(self) -> Keyboard<T> {
+        Keyboard {
+            metadata: self.metadata,
+            keys: self.keys,
+        }
+    }
+}
+
+impl<T> Keyboard<T>
+where
+    T: Real,
+{
+    /// Creates a [`LayoutBuilder`] seeded with the keyboard's metadata and keys, allowing the
+    /// keyboard to be edited and rebuilt.
+    ///
+    /// The cursor is placed to the right of the last key, so keys added with
+    /// [`LayoutBuilder::next_key`] continue the last row.
+    ///
+    /// Since each key's properties are stored in full, keys can be freely edited using
+    /// [`LayoutBuilder::keys_mut`] before the keyboard is serialised again. When serialising, KLE's
+    /// relative properties are recalculated from the differences between consecutive keys, so
+    /// editing a key will never change the position or appearance of any other keys. Keys are
+    /// serialised in the order they are in, so placing a key before the previous key in the same
+    /// row will result in a negative `x` offset.
+    #[must_use]
+    pub fn to_builder(self) -> LayoutBuilder<T> {
+        LayoutBuilder::new().metadata(self.metadata).keys(self.keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use isclose::assert_is_close;
+
+    use super::*;
+
+    #[test]
+    fn test_layout_builder() {
+        let kb = LayoutBuilder::<f64>::new()
+            .metadata(Metadata {
+                name: "test".into(),
+                ..Metadata::default()
+            })
+            .next_key(Key::default())
+            .next_key(Key {
+                width: 2.0,
+                ..Key::default()
+            })
+            .next_key(Key::default())
+            .new_row()
+            .next_key(Key {
+                x: 5.0,
+                ..Key::default()
+            })
+            .key(Key {
+                x: 3.0,
+                y: 4.0,
+                ..Key::default()
+            })
+            .next_key(Key::default())
+            .build();
+
+        assert_eq!(kb.metadata.name, "test");
+        assert_eq!(kb.keys.len(), 6);
+
+        let expected = [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (3.0, 0.0),
+            (0.0, 1.0),
+            (3.0, 4.0),
+            (4.0, 4.0),
+        ];
+        for (key, (x, y)) in kb.keys.iter().zip(expected) {
+            assert_is_close!(key.x, x);
+            assert_is_close!(key.y, y);
+        }
+
+        let kb = <LayoutBuilder>::new().build();
+        assert!(kb.keys.is_empty());
+    }
+
+    #[test]
+    fn test_layout_builder_new_row_rotated() {
+        let kb = LayoutBuilder::<f64>::new()
+            .next_key(Key {
+                rotation: 15.0,
+                rx: 2.0,
+                ry: 1.0,
+                ..Key::default()
+            })
+            .new_row()
+            .next_key(Key::default())
+            .build();
+
+        assert_is_close!(kb.keys[1].x, 2.0);
+        assert_is_close!(kb.keys[1].y, 1.0);
+    }
+
+    #[test]
+    fn test_layout_builder_stepped_key() {
+        let stepped = Key {
+            width: 1.25,
+            width2: 1.75,
+            ..Key::default()
+        };
+        let kb = LayoutBuilder::<f64>::new()
+            .next_key(stepped.clone())
+            .next_key(Key::default())
+            .build();
+
+        // The cursor moves past the wider secondary rectangle, matching the deserialiser
+        let expected: Keyboard =
+            serde_json::from_str(r#"[[{"w": 1.25, "w2": 1.75}, "", ""]]"#).unwrap();
+        assert_is_close!(kb.keys[1].x, 1.75);
+        assert_eq!(kb.keys, expected.keys);
+        assert_eq!(kb.keys[0], stepped);
+    }
+
+    #[test]
+    fn test_keyboard_to_builder() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                {"name": "test"},
+                ["A", "B"],
+                ["C"]
+            ]"#,
+        )
+        .unwrap();
+
+        let mut builder = kb.clone().to_builder();
+        assert_eq!(builder.clone().build(), kb);
+
+        let keys = builder.keys_mut();
+        keys[0].width = 1.5;
+        keys[0].width2 = 1.5;
+        keys[1].x = 1.5;
+        let edited = builder.next_key(Key::default()).build();
+
+        assert_eq!(edited.metadata.name, "test");
+        assert_eq!(edited.keys.len(), 4);
+        assert_is_close!(edited.keys[3].x, 1.0);
+        assert_is_close!(edited.keys[3].y, 1.0);
+
+        let json = serde_json::to_string(&edited).unwrap();
+        assert_eq!(json, r#"[{"name":"test"},[{"w":1.5},"A","B"],["C",""]]"#);
+    }
+}
@@ -0,0 +1,160 @@
+use std::cmp::Ordering;
+
+use num_traits::real::Real;
+
+use crate::{utils::real, Key, Keyboard};
+
+// The grid positions and sizes are snapped to in keyboard units
+const CANONICAL_GRID: f64 = 1e-4;
+
+fn snap<T>(value: T) -> T
+where
+    T: Real,
+{
+    let grid = real(CANONICAL_GRID);
+    let snapped = (value / grid).round() * grid;
+    // Avoid returning negative zero, which would make otherwise equal keys compare differently
+    // when serialised
+    if snapped.is_zero() {
+        T::zero()
+    } else {
+        snapped
+    }
+}
+
+fn canonical_key<T>(key: &Key<T>) -> Key<T>
+where
+    T: Real,
+{
+    let full_turn = real(360.0);
+    let rotation = snap(key.rotation - (key.rotation / full_turn).floor() * full_turn);
+    let rotation = if rotation == full_turn {
+        T::zero()
+    } else {
+        rotation
+    };
+
+    // The centre of rotation has no effect on unrotated keys
+    let (rx, ry) = if rotation.is_zero() {
+        (T::zero(), T::zero())
+    } else {
+        (snap(key.rx), snap(key.ry))
+    };
+
+    let mut legends = key.legends.clone();
+    for legend in &mut legends {
+        if legend
+            .as_ref()
+            .map_or(false, |legend| legend.text.is_empty())
+        {
+            *legend = None;
+        }
+    }
+
+    Key {
+        legends,
+        x: snap(key.x),
+        y: snap(key.y),
+        width: snap(key.width),
+        height: snap(key.height),
+        x2: snap(key.x2),
+        y2: snap(key.y2),
+        width2: snap(key.width2),
+        height2: snap(key.height2),
+        rotation,
+        rx,
+        ry,
+        ..key.clone()
+    }
+}
+
+impl<T> Keyboard<T>
+where
+    T: Real,
+{
+    /// Returns a canonical copy of the keyboard, which can be used to compare or deduplicate
+    /// layouts regardless of how they were encoded.
+    ///
+    /// In the canonical form:
+    ///
+    /// * positions, sizes, and centres of rotation are snapped to a grid of 0.0001 keyboard units;
+    /// * rotations are normalised to the range [0, 360), and unrotated keys have their centre of
+    ///   rotation set to the origin;
+    /// * legends with empty text are removed; and
+    /// * keys are sorted into reading order by their centre, top to bottom and then left to right.
+    ///   Keys with the same centre keep their relative order.
+    ///
+    /// Colours are always stored as RGBA values, so colours written using different CSS syntaxes
+    /// (for example `#f00`, `#ff0000`, and `red`) are already equal. Metadata is kept as is.
+    #[must_use]
+    pub fn canonical_form(&self) -> Self {
+        let mut keys: Vec<_> = (self.keys.iter())
+            .map(|key| {
+                let key = canonical_key(key);
+                let (x, y) = key.center();
+                ((snap(y), snap(x)), key)
+            })
+            .collect();
+        keys.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        Self {
+            metadata: self.metadata.clone(),
+            keys: keys.into_iter().map(|(_, key)| key).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use isclose::assert_is_close;
+
+    use super::*;
+    use crate::Legend;
+
+    #[test]
+    fn test_snap() {
+        assert_is_close!(snap(0.123_456_7), 0.1235);
+        assert_is_close!(snap(-1.000_01), -1.0);
+        assert!(snap(-0.000_01_f64).is_sign_positive());
+    }
+
+    #[test]
+    fn test_keyboard_canonical_form() {
+        let kb: Keyboard = serde_json::from_str(
+            r##"[
+                {"name": "test"},
+                [{"c": "#ff0000"}, "A", {"w": 2}, "B"],
+                [{"r": 30, "rx": 1, "ry": 2}, "C"],
+                ["D"]
+            ]"##,
+        )
+        .unwrap();
+
+        // The same layout, with keys in a different order, different colour syntax, a full turn
+        // added to the rotation, floating point error, and an explicit empty legend
+        let mut other: Keyboard = serde_json::from_str(
+            r##"[
+                {"name": "test"},
+                [{"x": 1.0000001, "c": "red", "w": 1.99999999}, "B"],
+                [{"r": 390, "rx": 1, "ry": 2}, "C"],
+                [{"r": 0, "rx": 5, "ry": 5, "x": -5, "y": -5, "c": "#f00"}, "A"],
+                [{"r": 30, "rx": 1, "ry": 2, "y": 1}, "D"]
+            ]"##,
+        )
+        .unwrap();
+        other.keys[1].legends[3] = Some(Legend {
+            text: String::new(),
+            ..Legend::default()
+        });
+
+        assert_ne!(kb, other);
+        assert_eq!(kb.canonical_form(), other.canonical_form());
+        assert_eq!(kb.canonical_form(), kb.canonical_form().canonical_form());
+
+        let canonical = kb.canonical_form();
+        let legends: Vec<_> = (canonical.keys.iter())
+            .map(|key| key.legends[0].as_ref().unwrap().text.as_str())
+            .collect();
+        assert_eq!(legends, ["A", "B", "C", "D"]);
+    }
+}
@@ -0,0 +1,87 @@
+use num_traits::real::Real;
+
+use crate::{Color, Keyboard};
+
+// Returns the relative luminance of a colour as defined by WCAG 2, ignoring alpha
+fn relative_luminance(color: Color) -> f64 {
+    let channel = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.040_45 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * channel(color.r) + 0.7152 * channel(color.g) + 0.0722 * channel(color.b)
+}
+
+// Returns the WCAG 2 contrast ratio between two colours, ranging from 1 (no contrast) to 21
+fn contrast_ratio(a: Color, b: Color) -> f64 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+impl<T> Keyboard<T>
+where
+    T: Real,
+{
+    /// Returns the legends whose colour has poor contrast against their key's colour, as
+    /// `(key index, legend index)` pairs.
+    ///
+    /// Contrast is measured using the [WCAG 2 contrast ratio][wcag], which ranges from 1 for
+    /// identical colours to 21 for black on white. Legends with a contrast ratio below `min_ratio`
+    /// are returned; WCAG recommends a minimum of 4.5 for normal text, or 3 for large text. The
+    /// alpha channel of both colours is ignored.
+    ///
+    /// [wcag]: https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio
+    #[must_use]
+    pub fn low_contrast_legends(&self, min_ratio: f64) -> Vec<(usize, usize)> {
+        (self.keys.iter().enumerate())
+            .flat_map(|(i, key)| {
+                key.legends_iter()
+                    .filter(|(_, legend)| contrast_ratio(legend.color, key.color) < min_ratio)
+                    .map(move |(j, _)| (i, j))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use isclose::assert_is_close;
+
+    use super::*;
+
+    #[test]
+    fn test_contrast_ratio() {
+        let (black, white) = (Color::new(0, 0, 0, 255), Color::new(255, 255, 255, 255));
+
+        assert_is_close!(contrast_ratio(black, white), 21.0);
+        assert_is_close!(contrast_ratio(white, black), 21.0);
+        assert_is_close!(contrast_ratio(black, black), 1.0);
+
+        // KLE's default legend and key colours
+        let ratio = contrast_ratio(black, Color::new(0xCC, 0xCC, 0xCC, 255));
+        assert!((13.0..13.1).contains(&ratio));
+    }
+
+    #[test]
+    fn test_keyboard_low_contrast_legends() {
+        let kb: Keyboard = serde_json::from_str(
+            r##"[
+                ["A", {"c": "#000000"}, "B\nC", {"t": "#ffffff\n#111111"}, "D\nE"],
+                [{"c": "#777777", "t": "#ffffff"}, "F"]
+            ]"##,
+        )
+        .unwrap();
+
+        // Black on black is flagged, as is the dark grey legend
+        assert_eq!(
+            kb.low_contrast_legends(4.5),
+            [(1, 0), (1, 6), (2, 6), (3, 0)]
+        );
+        assert_eq!(kb.low_contrast_legends(4.0), [(1, 0), (1, 6), (2, 6)]);
+        assert!(kb.low_contrast_legends(1.0).is_empty());
+    }
+}
@@ -3,19 +3,32 @@ use std::{fmt, marker::PhantomData};
 use csscolorparser::Color as CssColor;
 use num_traits::real::Real;
 use serde::{
-    de::{Error, SeqAccess, Unexpected, Visitor},
+    de::{
+        value::MapAccessDeserializer, DeserializeSeed, Error, IgnoredAny, IntoDeserializer,
+        MapAccess, SeqAccess, Unexpected, Visitor,
+    },
     Deserialize, Deserializer,
 };
 
-use crate::{
-    utils::{Alignment, FontSize},
-    Color,
-};
+use crate::{utils::Alignment, Color};
+
+// Fast path for the #rrggbb colours KLE itself emits, avoiding the overhead of a full CSS parser
+fn color_from_hex(value: &str) -> Option<Color> {
+    let hex = (value.strip_prefix('#'))
+        .filter(|hex| hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()))?;
+    let channel = |i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+
+    Some(Color::new(channel(0)?, channel(2)?, channel(4)?, u8::MAX))
+}
 
 fn color_from_str<'de, D>(value: &str) -> Result<Color, D::Error>
 where
     D: Deserializer<'de>,
 {
+    if let Some(color) = color_from_hex(value) {
+        return Ok(color);
+    }
+
     csscolorparser::parse(value)
         .map(|c| CssColor::to_rgba8(&c))
         .map(|[r, g, b, a]| Color { r, g, b, a })
@@ -73,10 +86,7 @@ pub(crate) struct KleMetadata {
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(default)]
-pub(crate) struct KlePropsObject<T = f64>
-where
-    T: Real,
-{
+pub(crate) struct KlePropsObject<T = f64> {
     pub x: Option<T>,
     pub y: Option<T>,
     pub w: Option<T>,
@@ -101,16 +111,14 @@ where
     pub t: Option<Vec<Option<Color>>>,
     pub a: Option<Alignment>,
     pub p: Option<String>,
-    pub f: Option<FontSize>,
-    pub f2: Option<FontSize>,
-    pub fa: Option<Vec<FontSize>>,
+    // Font sizes are clamped later, since the range can be configured
+    pub f: Option<usize>,
+    pub f2: Option<usize>,
+    pub fa: Option<Vec<usize>>,
 }
 
 // Can't derive Default unless we add T: Default trait bound
-impl<T> Default for KlePropsObject<T>
-where
-    T: Real,
-{
+impl<T> Default for KlePropsObject<T> {
     fn default() -> Self {
         Self {
             x: None,
@@ -142,9 +150,103 @@ where
     }
 }
 
+// A number which may also be given as a numeric string, used in tolerant mode
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LenientNumber<T>(T);
+
+impl<'de, T> Deserialize<'de> for LenientNumber<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct LenientNumberVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for LenientNumberVisitor<T>
+        where
+            T: Deserialize<'de>,
+        {
+            type Value = LenientNumber<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number or a numeric string")
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                T::deserialize(v.into_deserializer()).map(LenientNumber)
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                T::deserialize(v.into_deserializer()).map(LenientNumber)
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                T::deserialize(v.into_deserializer()).map(LenientNumber)
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                // f64's FromStr also accepts values like "NaN" and "inf", which are never valid
+                let number = (v.trim().parse())
+                    .ok()
+                    .filter(|number: &f64| number.is_finite())
+                    .ok_or_else(|| E::invalid_value(Unexpected::Str(v), &"a numeric string"))?;
+                self.visit_f64(number)
+            }
+        }
+
+        deserializer.deserialize_any(LenientNumberVisitor(PhantomData))
+    }
+}
+
+impl<T> From<KlePropsObject<LenientNumber<T>>> for KlePropsObject<T> {
+    fn from(value: KlePropsObject<LenientNumber<T>>) -> Self {
+        let number = |n: Option<LenientNumber<T>>| n.map(|LenientNumber(n)| n);
+        Self {
+            x: number(value.x),
+            y: number(value.y),
+            w: number(value.w),
+            h: number(value.h),
+            x2: number(value.x2),
+            y2: number(value.y2),
+            w2: number(value.w2),
+            h2: number(value.h2),
+            r: number(value.r),
+            rx: number(value.rx),
+            ry: number(value.ry),
+            l: value.l,
+            n: value.n,
+            d: value.d,
+            g: value.g,
+            sm: value.sm,
+            sb: value.sb,
+            st: value.st,
+            c: value.c,
+            t: value.t,
+            a: value.a,
+            p: value.p,
+            f: value.f,
+            f2: value.f2,
+            fa: value.fa,
+        }
+    }
+}
+
 // Represents either a key or a JSON object containing properties for the next key(s)
-#[derive(Deserialize, Debug, Clone)]
-#[serde(untagged)]
+#[derive(Debug, Clone)]
 pub(crate) enum KleLegendsOrProps<T = f64>
 where
     T: Real,
@@ -153,6 +255,15 @@ where
     Legend(String),
 }
 
+// Represents either a row of the layout or the metadata object
+enum KleRowOrMetadata<T>
+where
+    T: Real,
+{
+    Row(Vec<KleLegendsOrProps<T>>),
+    Metadata(Box<KleMetadata>),
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct KleKeyboard<T = f64>
 where
@@ -162,67 +273,287 @@ where
     pub layout: Vec<Vec<KleLegendsOrProps<T>>>,
 }
 
-impl<'de, T> Deserialize<'de> for KleKeyboard<T>
+// Deserialises a value of type V, optionally in tolerant mode. In tolerant mode numeric strings
+// are accepted for key positions, sizes and rotations, and the metadata object may be at the end
+// of the layout
+pub(crate) struct KleSeed<V> {
+    tolerant: bool,
+    marker: PhantomData<V>,
+}
+
+impl<V> KleSeed<V> {
+    pub fn new(tolerant: bool) -> Self {
+        Self {
+            tolerant,
+            marker: PhantomData,
+        }
+    }
+
+    fn seed<U>(&self) -> KleSeed<U> {
+        KleSeed::new(self.tolerant)
+    }
+}
+
+// We implement these manually rather than using #[serde(untagged)] since untagged enums buffer
+// their input, which is significantly slower
+impl<'de, T> DeserializeSeed<'de> for KleSeed<KleLegendsOrProps<T>>
 where
     T: Real + Deserialize<'de>,
 {
-    fn deserialize<D>(deserializer: D) -> Result<KleKeyboard<T>, D::Error>
+    type Value = KleLegendsOrProps<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct KleFileVisitor<T>(PhantomData<T>);
+        deserializer.deserialize_any(self)
+    }
+}
 
-        impl<'de, T> Visitor<'de> for KleFileVisitor<T>
-        where
-            T: Real + Deserialize<'de>,
-        {
-            type Value = KleKeyboard<T>;
+impl<'de, T> Visitor<'de> for KleSeed<KleLegendsOrProps<T>>
+where
+    T: Real + Deserialize<'de>,
+{
+    type Value = KleLegendsOrProps<T>;
 
-            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("a sequence")
-            }
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string or a map")
+    }
 
-            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
-            where
-                A: SeqAccess<'de>,
-            {
-                // The order in this enum is important. Serde will try to deserialize a vec first,
-                // otherwise a struct. This is important since you can deserialise a JSON sequence
-                // to a struct but not a JSON object to a Vec.
-                #[derive(Deserialize)]
-                #[serde(untagged)]
-                enum MapOrSeq<T>
-                where
-                    T: Real,
-                {
-                    Seq(Vec<KleLegendsOrProps<T>>),
-                    Map(Box<KleMetadata>),
-                }
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        Ok(KleLegendsOrProps::Legend(v.into()))
+    }
 
-                // Set a max initial size of 2**12, this is also what serde does internally
-                let mut layout = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        Ok(KleLegendsOrProps::Legend(v))
+    }
 
-                let meta = match seq.next_element()? {
-                    Some(MapOrSeq::Map(meta)) => *meta,
-                    Some(MapOrSeq::Seq(row)) => {
-                        layout.push(row);
-                        KleMetadata::default()
-                    }
-                    None => KleMetadata::default(),
-                };
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let deserializer = MapAccessDeserializer::new(map);
+        let props = if self.tolerant {
+            KlePropsObject::<LenientNumber<T>>::deserialize(deserializer)?.into()
+        } else {
+            KlePropsObject::deserialize(deserializer)?
+        };
+        Ok(KleLegendsOrProps::Props(Box::new(props)))
+    }
+}
 
-                while let Some(row) = seq.next_element()? {
-                    layout.push(row);
-                }
+impl<'de, T> DeserializeSeed<'de> for KleSeed<Vec<KleLegendsOrProps<T>>>
+where
+    T: Real + Deserialize<'de>,
+{
+    type Value = Vec<KleLegendsOrProps<T>>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T> Visitor<'de> for KleSeed<Vec<KleLegendsOrProps<T>>>
+where
+    T: Real + Deserialize<'de>,
+{
+    type Value = Vec<KleLegendsOrProps<T>>;
 
-                Ok(Self::Value { meta, layout })
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        // Set a max initial size of 2**12, this is also what serde does internally
+        let mut row = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(element) = seq.next_element_seed(self.seed::<KleLegendsOrProps<T>>())? {
+            row.push(element);
+        }
+        Ok(row)
+    }
+}
+
+impl<'de, T> DeserializeSeed<'de> for KleSeed<KleRowOrMetadata<T>>
+where
+    T: Real + Deserialize<'de>,
+{
+    type Value = KleRowOrMetadata<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, T> Visitor<'de> for KleSeed<KleRowOrMetadata<T>>
+where
+    T: Real + Deserialize<'de>,
+{
+    type Value = KleRowOrMetadata<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence or a map")
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let row = self.seed::<Vec<KleLegendsOrProps<T>>>().visit_seq(seq)?;
+        Ok(KleRowOrMetadata::Row(row))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let meta = KleMetadata::deserialize(MapAccessDeserializer::new(map))?;
+        Ok(KleRowOrMetadata::Metadata(Box::new(meta)))
+    }
+}
+
+impl<'de, T> DeserializeSeed<'de> for KleSeed<KleKeyboard<T>>
+where
+    T: Real + Deserialize<'de>,
+{
+    type Value = KleKeyboard<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T> Visitor<'de> for KleSeed<KleKeyboard<T>>
+where
+    T: Real + Deserialize<'de>,
+{
+    type Value = KleKeyboard<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        // Set a max initial size of 2**12, this is also what serde does internally
+        let mut layout = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+
+        let mut meta = match seq.next_element_seed(self.seed::<KleRowOrMetadata<T>>())? {
+            Some(KleRowOrMetadata::Metadata(meta)) => Some(*meta),
+            Some(KleRowOrMetadata::Row(row)) => {
+                layout.push(row);
+                None
+            }
+            None => None,
+        };
+
+        if self.tolerant {
+            // Accept a trailing metadata object, but only as the very last element. A
+            // leading metadata object takes precedence
+            let mut trailing = None;
+            while let Some(element) = seq.next_element_seed(self.seed::<KleRowOrMetadata<T>>())? {
+                if trailing.is_some() {
+                    return Err(A::Error::custom(
+                        "the metadata object must be the first or last element",
+                    ));
+                }
+                match element {
+                    KleRowOrMetadata::Row(row) => layout.push(row),
+                    KleRowOrMetadata::Metadata(m) => trailing = Some(*m),
+                }
+            }
+            meta = meta.or(trailing);
+        } else {
+            while let Some(row) = seq.next_element_seed(self.seed::<Vec<KleLegendsOrProps<T>>>())? {
+                layout.push(row);
             }
         }
 
-        deserializer.deserialize_seq(KleFileVisitor(PhantomData))
+        Ok(Self::Value {
+            meta: meta.unwrap_or_default(),
+            layout,
+        })
+    }
+}
+
+impl<'de, T> Deserialize<'de> for KleKeyboard<T>
+where
+    T: Real + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<KleKeyboard<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        KleSeed::<Self>::new(false).deserialize(deserializer)
     }
 }
 
+// Deserialises the value of the given key in a map, ignoring all other keys. This is used for
+// layouts embedded in some other object
+pub(crate) fn de_wrapped<'de, D, S>(
+    deserializer: D,
+    key: &str,
+    seed: S,
+) -> Result<S::Value, D::Error>
+where
+    D: Deserializer<'de>,
+    S: DeserializeSeed<'de>,
+{
+    struct WrappedVisitor<'a, S>(&'a str, S);
+
+    impl<'de, S> Visitor<'de> for WrappedVisitor<'_, S>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        type Value = S::Value;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a map containing the key `{}`", self.0)
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let (name, mut seed) = (self.0, Some(self.1));
+            let mut value = None;
+
+            while let Some(key) = map.next_key::<String>()? {
+                match seed.take() {
+                    Some(s) if key == name => value = Some(map.next_value_seed(s)?),
+                    other => {
+                        seed = other;
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                }
+            }
+
+            value.ok_or_else(|| A::Error::custom(format!("missing field `{name}`")))
+        }
+    }
+
+    deserializer.deserialize_map(WrappedVisitor(key, seed))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -230,6 +561,23 @@ mod tests {
     use assert_matches::assert_matches;
     use serde_json::{Deserializer, Error};
 
+    #[test]
+    fn test_color_from_hex() {
+        assert_eq!(
+            color_from_hex("#ff0099"),
+            Some(Color::new(255, 0, 153, 255))
+        );
+        assert_eq!(
+            color_from_hex("#FF0099"),
+            Some(Color::new(255, 0, 153, 255))
+        );
+        assert_eq!(color_from_hex("#f09"), None);
+        assert_eq!(color_from_hex("#ff0099cc"), None);
+        assert_eq!(color_from_hex("ff0099"), None);
+        assert_eq!(color_from_hex("#+f+f+f"), None);
+        assert_eq!(color_from_hex("#ff00é"), None);
+    }
+
     #[test]
     fn test_de_color() {
         let colors = [
@@ -253,6 +601,29 @@ mod tests {
                 .unwrap();
             assert_eq!(color, res);
         }
+
+        let colors = [
+            ("rgb(204,204,204)", Color::new(204, 204, 204, 255)),
+            ("rgba(238, 238, 238, 1)", Color::new(238, 238, 238, 255)),
+            ("rgba(255, 0, 153, 0.5)", Color::new(255, 0, 153, 128)),
+            ("rgba(255, 0, 153, 0)", Color::new(255, 0, 153, 0)),
+        ];
+
+        for (css, res) in colors {
+            let color = de_color(&mut Deserializer::from_str(&format!(r#""{css}""#)))
+                .unwrap()
+                .unwrap();
+            assert_eq!(color, res);
+        }
+
+        for css in [
+            "rgb(204, 204)",
+            "rgba(1, 2, 3, 4, 5)",
+            "rgb(1, 2, 3",
+            "rgb(a, b, c)",
+        ] {
+            assert!(de_color(&mut Deserializer::from_str(&format!(r#""{css}""#))).is_err());
+        }
     }
 
     #[test]
@@ -311,4 +682,32 @@ mod tests {
 
         assert_matches!(serde_json::from_str::<KleKeyboard>("null"), Err(_));
     }
+
+    #[test]
+    fn test_de_wrapped() {
+        let result: KleKeyboard = de_wrapped(
+            &mut Deserializer::from_str(
+                r#"{"name": "wrapper", "layout": [{"name": "test"}, ["A"]], "id": 1}"#,
+            ),
+            "layout",
+            PhantomData,
+        )
+        .unwrap();
+        assert_matches!(result.meta.name, Some(name) if name == "test");
+        assert_eq!(result.layout.len(), 1);
+
+        let result: Result<KleKeyboard, _> = de_wrapped(
+            &mut Deserializer::from_str(r#"{"name": "wrapper"}"#),
+            "layout",
+            PhantomData,
+        );
+        assert_matches!(result, Err(e) if e.to_string().contains("missing field `layout`"));
+
+        let result: Result<KleKeyboard, _> = de_wrapped(
+            &mut Deserializer::from_str(r#"[["A"]]"#),
+            "layout",
+            PhantomData,
+        );
+        assert_matches!(result, Err(_));
+    }
 }
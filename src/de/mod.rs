@@ -5,12 +5,30 @@ use std::vec;
 use crate::{
     color,
     utils::{realign_legends, Alignment, FontSize},
-    Background, Color, Key, Legend, Metadata, Switch, NUM_LEGENDS,
+    Background, Color, DeserializeOptions, Key, Legend, Metadata, Switch, NUM_LEGENDS,
 };
-use json::{KleBackground, KleLegendsOrProps, KleMetadata, KlePropsObject};
+use json::{KleBackground, KleLegendsOrProps, KleMetadata, KlePropsObject, KleSeed};
 
 pub(crate) use json::KleKeyboard;
 use num_traits::real::Real;
+use serde::{de::DeserializeSeed, Deserialize, Deserializer};
+
+// Deserialises the raw KLE data, taking into account any options that affect the structure of the
+// input
+pub(crate) fn deserialize_kle<'de, D, T>(
+    deserializer: D,
+    options: &DeserializeOptions,
+) -> Result<KleKeyboard<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Real + Deserialize<'de>,
+{
+    let seed = KleSeed::<KleKeyboard<T>>::new(options.tolerant);
+    match options.wrapper_key {
+        Some(ref key) => json::de_wrapped(deserializer, key, seed),
+        None => seed.deserialize(deserializer),
+    }
+}
 
 impl From<KleBackground> for Background {
     fn from(value: KleBackground) -> Self {
@@ -69,17 +87,17 @@ where
     r: T,
     rx: T,
     ry: T,
-    g: bool,                     // ghosted
-    sm: String,                  // switch mount
-    sb: String,                  // switch brand
-    st: String,                  // switch type
-    c: Color,                    // color
-    t: Color,                    // fallback legend color
-    ta: [Color; NUM_LEGENDS],    // legend color array
-    a: Alignment,                // alignment
-    p: String,                   // profile
-    f: FontSize,                 // fallback font size
-    fa: [FontSize; NUM_LEGENDS], // font size array
+    g: bool,                  // ghosted
+    sm: String,               // switch mount
+    sb: String,               // switch brand
+    st: String,               // switch type
+    c: Color,                 // color
+    t: Color,                 // fallback legend color
+    ta: [Color; NUM_LEGENDS], // legend color array
+    a: Alignment,             // alignment
+    p: String,                // profile
+    f: usize,                 // fallback font size
+    fa: [usize; NUM_LEGENDS], // font size array
 }
 
 impl<T> KleProps<T>
@@ -90,7 +108,7 @@ where
         let f = props.f.unwrap_or(self.f);
         let fa = if let Some(fa) = props.fa {
             std::array::from_fn(|i| match fa.get(i).copied() {
-                Some(fa) if usize::from(fa) > 0 => fa,
+                Some(fa) if fa > 0 => fa,
                 _ => f,
             })
         } else if let Some(f2) = props.f2 {
@@ -172,17 +190,15 @@ where
     }
 
     fn build_key(&self, legends: &str) -> Key<T> {
-        let legends =
-            legends
-                .lines()
-                .zip(self.fa.into_iter().zip(self.ta))
-                .map(|(text, (size, color))| {
-                    (!text.is_empty()).then_some(Legend {
-                        text: text.into(),
-                        size: usize::from(size),
-                        color,
-                    })
-                });
+        let legends = split_legends(legends)
+            .zip(self.fa.into_iter().zip(self.ta))
+            .map(|(text, (size, color))| {
+                (!text.is_empty()).then_some(Legend {
+                    text: text.into(),
+                    size,
+                    color,
+                })
+            });
         let legends = realign_legends(legends, self.a);
 
         Key {
@@ -242,8 +258,8 @@ where
             ta: [color::LEGEND; NUM_LEGENDS],
             a: Alignment::default(),
             p: String::new(),
-            f: FontSize::default(),
-            fa: [FontSize::default(); NUM_LEGENDS],
+            f: usize::from(FontSize::default()),
+            fa: [usize::from(FontSize::default()); NUM_LEGENDS],
         }
     }
 }
@@ -256,13 +272,14 @@ where
     state: KleProps<T>,
     row_iter: vec::IntoIter<Vec<KleLegendsOrProps<T>>>,
     key_iter: vec::IntoIter<KleLegendsOrProps<T>>,
+    options: DeserializeOptions,
 }
 
 impl<T> KleLayoutIterator<T>
 where
     T: Real,
 {
-    pub(crate) fn new(kle: Vec<Vec<KleLegendsOrProps<T>>>) -> Self {
+    pub(crate) fn new(kle: Vec<Vec<KleLegendsOrProps<T>>>, options: DeserializeOptions) -> Self {
         let state = KleProps::default();
         let mut row_iter = kle.into_iter();
         let key_iter = row_iter.next().unwrap_or_default().into_iter();
@@ -270,10 +287,32 @@ where
             state,
             row_iter,
             key_iter,
+            options,
         }
     }
 }
 
+// Splits a KLE legend string into the legends for each slot. KLE uses \n as a separator, but we
+// also accept \r\n and \r since files edited on other platforms may contain them
+fn split_legends(legends: &str) -> impl Iterator<Item = &str> {
+    let mut rest = Some(legends);
+    std::iter::from_fn(move || {
+        let legends = rest?;
+        if let Some(i) = legends.find(&['\r', '\n'][..]) {
+            let sep_len = if legends[i..].starts_with("\r\n") {
+                2
+            } else {
+                1
+            };
+            rest = Some(&legends[i + sep_len..]);
+            Some(&legends[..i])
+        } else {
+            rest = None;
+            Some(legends)
+        }
+    })
+}
+
 impl<T> Iterator for KleLayoutIterator<T>
 where
     T: Real,
@@ -294,9 +333,25 @@ where
             }
         };
 
-        let key = self.state.build_key(&legends);
+        let mut key = self.state.build_key(&legends);
         self.state.next_key();
 
+        if key.profile.is_empty() {
+            if let Some(ref profile) = self.options.default_profile {
+                key.profile.clone_from(profile);
+            }
+        }
+
+        let (min, max) = self.options.font_size_range.clone().into_inner();
+        for legend in key.legends.iter_mut().flatten() {
+            // Avoid clamp() since it panics if min > max
+            legend.size = legend.size.max(min).min(max);
+        }
+
+        if self.options.rotation_in_radians {
+            key.rotation = key.rotation.to_degrees();
+        }
+
         Some(key)
     }
 }
@@ -306,6 +361,7 @@ mod tests {
     use isclose::assert_is_close;
 
     use super::*;
+    use crate::utils::unalign_legends;
 
     #[test]
     fn test_background_from() {
@@ -428,8 +484,8 @@ mod tests {
         assert_eq!(props.ta, [color::LEGEND; NUM_LEGENDS]);
         assert_eq!(props.a, Alignment::default());
         assert_eq!(props.p, "");
-        assert_eq!(props.f, FontSize::default());
-        assert_eq!(props.fa, [FontSize::default(); NUM_LEGENDS]);
+        assert_eq!(props.f, usize::from(FontSize::default()));
+        assert_eq!(props.fa, [usize::from(FontSize::default()); NUM_LEGENDS]);
 
         let props_obj = KlePropsObject {
             x: Some(1.0),
@@ -458,9 +514,9 @@ mod tests {
             ]),
             a: Some(Alignment::new(5).unwrap()),
             p: Some("DSA".into()),
-            f: Some(FontSize::new(4).unwrap()),
-            f2: Some(FontSize::new(4).unwrap()),
-            fa: Some(vec![FontSize::new(4).unwrap(); 3]),
+            f: Some(4),
+            f2: Some(4),
+            fa: Some(vec![4; 3]),
         };
         props.update(props_obj);
 
@@ -503,26 +559,23 @@ mod tests {
         );
         assert_eq!(usize::from(props.a), 5);
         assert_eq!(props.p, "DSA");
-        assert_eq!(usize::from(props.f), 4);
-        assert_eq!(props.fa.map(usize::from), [4; NUM_LEGENDS]);
+        assert_eq!(props.f, 4);
+        assert_eq!(props.fa, [4; NUM_LEGENDS]);
 
         let props_obj = KlePropsObject {
-            f: Some(FontSize::new(2).unwrap()),
-            f2: Some(FontSize::new(4).unwrap()),
+            f: Some(2),
+            f2: Some(4),
             ..KlePropsObject::default()
         };
         props.update(props_obj);
-        assert_eq!(
-            props.fa.map(usize::from),
-            [2, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4]
-        );
+        assert_eq!(props.fa, [2, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4]);
 
         let rawprops4 = KlePropsObject {
-            f: Some(FontSize::new(5).unwrap()),
+            f: Some(5),
             ..KlePropsObject::default()
         };
         props.update(rawprops4);
-        assert_eq!(props.fa.map(usize::from), [5; NUM_LEGENDS]);
+        assert_eq!(props.fa, [5; NUM_LEGENDS]);
     }
 
     #[test]
@@ -646,7 +699,7 @@ mod tests {
         )
         .unwrap();
 
-        let iterator = KleLayoutIterator::new(kle.layout);
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
         let keys: Vec<_> = iterator.collect();
 
         assert_eq!(keys.len(), 4);
@@ -655,4 +708,266 @@ mod tests {
         assert_is_close!(keys[2].x, 1.5);
         assert_is_close!(keys[3].x, 0.0);
     }
+
+    #[test]
+    fn test_kle_layout_iterator_negative_offsets() {
+        let kle: KleKeyboard = serde_json::from_str(
+            r#"[
+                ["A", "B", "C"],
+                [{"y": -0.5, "x": 0.5}, "D", {"x": -2}, "E"],
+                [{"y": -1.5}, "F"]
+            ]"#,
+        )
+        .unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let keys: Vec<_> = iterator.collect();
+        assert_eq!(keys.len(), 6);
+
+        // Negative offsets move the cursor up and left without being clamped to zero
+        let positions = [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (2.0, 0.0),
+            (0.5, 0.5),
+            (-0.5, 0.5),
+            (0.0, 0.0),
+        ];
+        for (key, (x, y)) in keys.iter().zip(positions) {
+            assert_is_close!(key.x, x);
+            assert_is_close!(key.y, y);
+        }
+    }
+
+    #[test]
+    fn test_kle_layout_iterator_secondary_reset() {
+        let kle: KleKeyboard = serde_json::from_str(
+            r#"[
+                [{"w": 1.25, "w2": 1.75, "l": true, "x2": 0.5, "y2": 0.25, "h2": 2, "d": true}, "A", "B"],
+                [{"w": 1.5}, "C", {"x2": -0.25, "w2": 1.5}, "D", "E"]
+            ]"#,
+        )
+        .unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let keys: Vec<_> = iterator.collect();
+        assert_eq!(keys.len(), 5);
+
+        assert_is_close!(keys[0].x2, 0.5);
+        assert_is_close!(keys[0].y2, 0.25);
+        assert_is_close!(keys[0].width2, 1.75);
+        assert_is_close!(keys[0].height2, 2.0);
+        assert!(keys[0].stepped && keys[0].decal);
+
+        // The secondary rectangle and the stepped and decal flags only apply to a single key, so
+        // the following keys' secondary rectangles match their primary rectangles
+        for key in [&keys[1], &keys[2], &keys[4]] {
+            assert_is_close!(key.x2, 0.0);
+            assert_is_close!(key.y2, 0.0);
+            assert_is_close!(key.width2, key.width);
+            assert_is_close!(key.height2, key.height);
+            assert!(!key.stepped && !key.decal);
+        }
+        // The cursor still moves past the end of the first key's secondary rectangle
+        assert_is_close!(keys[1].x, 2.25);
+        assert_is_close!(keys[2].width2, 1.5);
+        assert_is_close!(keys[3].x2, -0.25);
+        assert_is_close!(keys[4].width, 1.0);
+    }
+
+    #[test]
+    fn test_kle_layout_iterator_alignment_persists() {
+        let kle: KleKeyboard = serde_json::from_str(
+            r#"[
+                [{"a": 5}, "A\nB"],
+                ["C\nD", {"a": 4}, "E\nF"],
+                ["G\nH"]
+            ]"#,
+        )
+        .unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let keys: Vec<_> = iterator.collect();
+        assert_eq!(keys.len(), 4);
+
+        // The alignment carries over into following rows until it is changed, with alignment 5
+        // placing the first two legends in slots 1 and 7, and alignment 4 in slots 0 and 6
+        for (key, (first, second)) in keys.iter().zip([(1, 7), (1, 7), (0, 6), (0, 6)]) {
+            let slots: Vec<_> = key.legends_iter().map(|(i, _)| i).collect();
+            assert_eq!(slots, [first, second]);
+        }
+        assert_eq!(keys[1].legends[1].as_ref().unwrap().text, "C");
+        assert_eq!(keys[1].legends[7].as_ref().unwrap().text, "D");
+    }
+
+    #[test]
+    fn test_kle_layout_iterator_empty_legends() {
+        let kle: KleKeyboard =
+            serde_json::from_str(r#"[["A\n\nB"], [{"a": 0}, "C\n\nD\n\n"]]"#).unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let keys: Vec<_> = iterator.collect();
+        assert_eq!(keys.len(), 2);
+
+        // Empty legends between populated ones are None rather than empty legends, both in slot
+        // order and in KLE's original order
+        for (key, alignment) in keys.iter().zip([4, 0]) {
+            assert_eq!(key.legends_iter().count(), 2);
+
+            let unaligned = unalign_legends(&key.legends, Alignment::new(alignment).unwrap());
+            let texts: Vec<_> = unaligned
+                .iter()
+                .take(3)
+                .map(|legend| legend.as_ref().map(|l| l.text.as_str()))
+                .collect();
+            assert!(texts[0].is_some() && texts[1].is_none() && texts[2].is_some());
+        }
+
+        // With the default alignment the second legend is in slot 6, and the third in slot 2
+        assert_eq!(keys[0].legends[0].as_ref().unwrap().text, "A");
+        assert!(keys[0].legends[6].is_none());
+        assert_eq!(keys[0].legends[2].as_ref().unwrap().text, "B");
+    }
+
+    #[test]
+    fn test_kle_layout_iterator_sparse_legends() {
+        let kle: KleKeyboard = serde_json::from_str(
+            r#"[
+                ["\n\n\n\n\n\n\n\n\nFn"],
+                [{"a": 7}, "\n\n\n\n\n\n\n\n\nFn"]
+            ]"#,
+        )
+        .unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let keys: Vec<_> = iterator.collect();
+        assert_eq!(keys.len(), 2);
+
+        // With the default alignment (4) the 10th legend in the string is the centre legend,
+        // while with alignment 7 it is the bottom right legend
+        for (key, slot) in keys.iter().zip([4, 8]) {
+            for (i, legend) in key.legends.iter().enumerate() {
+                if i == slot {
+                    assert_eq!(legend.as_ref().unwrap().text, "Fn");
+                } else {
+                    assert!(legend.is_none());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_kle_layout_iterator_text_color_default() {
+        let kle: KleKeyboard = serde_json::from_str(
+            r##"[
+                [{"t": "#ff0000\n\n#00ff00"}, "A\nB\nC", {"t": "\n#0000ff"}, "D\nE"]
+            ]"##,
+        )
+        .unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let keys: Vec<_> = iterator.collect();
+        let colors = |key: &Key| -> Vec<_> {
+            unalign_legends(&key.legends, Alignment::default())
+                .iter()
+                .map(|legend| legend.map(|l| l.color))
+                .collect()
+        };
+
+        let (red, green, blue) = (
+            Color::new(255, 0, 0, 255),
+            Color::new(0, 255, 0, 255),
+            Color::new(0, 0, 255, 255),
+        );
+
+        // Empty entries use the first colour in t, not black
+        assert_eq!(colors(&keys[0])[..3], [Some(red), Some(red), Some(green)]);
+
+        // The default persists when a later t has an empty first entry
+        assert_eq!(colors(&keys[1])[..2], [Some(red), Some(blue)]);
+    }
+
+    #[test]
+    fn test_kle_layout_iterator_color_persistence() {
+        let kle: KleKeyboard = serde_json::from_str(
+            r##"[
+                [{"c": "#ff0000", "t": "#00ff00"}, "A", "B", "C"],
+                ["D", {"c": "#0000ff"}, "E"],
+                ["F"]
+            ]"##,
+        )
+        .unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let keys: Vec<_> = iterator.collect();
+
+        let (red, green, blue) = (
+            Color::new(255, 0, 0, 255),
+            Color::new(0, 255, 0, 255),
+            Color::new(0, 0, 255, 255),
+        );
+
+        let colors: Vec<_> = keys.iter().map(|key| key.color).collect();
+        assert_eq!(colors, [red, red, red, red, blue, blue]);
+
+        let text_colors: Vec<_> = keys
+            .iter()
+            .map(|key| key.legends[0].as_ref().unwrap().color)
+            .collect();
+        assert_eq!(text_colors, [green; 6]);
+    }
+
+    #[test]
+    fn test_split_legends() {
+        let split = |legends| split_legends(legends).collect::<Vec<_>>();
+
+        assert_eq!(split("A\nB\n\nC"), ["A", "B", "", "C"]);
+        assert_eq!(split("A\r\nB\r\n\r\nC"), ["A", "B", "", "C"]);
+        assert_eq!(split("A\rB\r\rC"), ["A", "B", "", "C"]);
+        assert_eq!(split("A\n\r\nB\r\n"), ["A", "", "B", ""]);
+        assert_eq!(split(""), [""]);
+    }
+
+    #[test]
+    fn test_kle_layout_iterator_crlf_legends() {
+        let kle: KleKeyboard = serde_json::from_str(
+            r#"[
+                ["A\r\nB\r\nC\r\n\r\n\r\n\r\nD", "A\rB\rC\r\r\r\rD"]
+            ]"#,
+        )
+        .unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let keys: Vec<_> = iterator.collect();
+
+        for key in keys {
+            let legends: Vec<_> = (key.legends.iter())
+                .map(|legend| legend.as_ref().map_or("", |l| l.text.as_str()))
+                .collect();
+            assert_eq!(
+                legends,
+                ["A", "", "C", "D", "", "", "B", "", "", "", "", ""]
+            );
+        }
+    }
+
+    #[test]
+    fn test_kle_layout_iterator_profile_persistence() {
+        let kle: KleKeyboard = serde_json::from_str(
+            r#"[
+                [{"p": "SA R3"}, "A", "B", {"w": 2}, "C"],
+                ["D", {"p": "SA R4"}, "E"],
+                ["F"]
+            ]"#,
+        )
+        .unwrap();
+
+        let iterator = KleLayoutIterator::new(kle.layout, DeserializeOptions::default());
+        let profiles: Vec<_> = iterator.map(|key| key.profile).collect();
+
+        assert_eq!(
+            profiles,
+            ["SA R3", "SA R3", "SA R3", "SA R3", "SA R4", "SA R4"]
+        );
+    }
 }
@@ -0,0 +1,487 @@
+//! Functions for exporting a [`Keyboard`] into formats used by other tools.
+
+use std::fmt::{self, Write};
+
+use num_traits::real::Real;
+
+use crate::{utils::to_f64, Keyboard};
+
+/// An error returned by [`to_matrix_csv`] when a key has no valid matrix position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatrixError {
+    /// The index of the offending key in [`Keyboard::keys`].
+    pub index: usize,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key {}: {}", self.index, self.message)
+    }
+}
+
+impl std::error::Error for MatrixError {}
+
+/// Options used by [`to_openscad_plate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenScadOptions {
+    /// The size of one keyboard unit in millimetres. The default is 19.05 mm.
+    pub unit_size: f64,
+    /// The size of the square switch cutouts in millimetres. The default is 14 mm, which fits
+    /// Cherry MX style switches.
+    pub cutout_size: f64,
+    /// The distance in millimetres between the layout's bounding box and the edge of the plate.
+    /// The default is 5 mm.
+    pub plate_margin: f64,
+}
+
+impl Default for OpenScadOptions {
+    fn default() -> Self {
+        Self {
+            unit_size: 19.05,
+            cutout_size: 14.0,
+            plate_margin: 5.0,
+        }
+    }
+}
+
+/// Options used by [`to_kicad_pcb_snippet`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct KicadOptions {
+    /// The size of one keyboard unit in millimetres. The default is 19.05 mm.
+    pub unit_size: f64,
+    /// The footprint placed for each key, as a `KiCad` library identifier. The default is
+    /// `Button_Switch_Keyboard:SW_Cherry_MX_1.00u_PCB` from `KiCad`'s standard library.
+    pub footprint: String,
+    /// The board layer the footprints are placed on. The default is `F.Cu`.
+    pub layer: String,
+    /// The prefix of each footprint's reference designator, which is followed by the key's
+    /// number starting from 1. The default is `SW`.
+    pub reference_prefix: String,
+}
+
+impl Default for KicadOptions {
+    fn default() -> Self {
+        Self {
+            unit_size: 19.05,
+            footprint: "Button_Switch_Keyboard:SW_Cherry_MX_1.00u_PCB".into(),
+            layer: "F.Cu".into(),
+            reference_prefix: "SW".into(),
+        }
+    }
+}
+
+// Formats a number for output, trimming any redundant trailing zeros
+fn fmt_num(value: f64) -> String {
+    let string = format!("{value:.4}");
+    let string = string.trim_end_matches('0').trim_end_matches('.');
+    match string {
+        "-0" => "0".into(),
+        string => string.into(),
+    }
+}
+
+/// Generates an [OpenSCAD] plate outline for the given keyboard.
+///
+/// The output is a 2D `difference()` of a rectangular plate minus a square switch cutout centred
+/// on each key, rotated to match the key's rotation. The plate covers the layout's
+/// [`bounding box`](Keyboard::bounding_box) plus [`OpenScadOptions::plate_margin`].
+///
+/// All dimensions are in millimetres. Since `OpenSCAD`'s Y axis points up rather than down, Y
+/// coordinates are negated so the plate is placed below the X axis. Decals are not physical keys
+/// and do not get cutouts.
+///
+/// The result can be turned into a 3D plate using `OpenSCAD`'s `linear_extrude`.
+///
+/// [OpenSCAD]: https://openscad.org/
+#[must_use]
+pub fn to_openscad_plate<T>(keyboard: &Keyboard<T>, options: &OpenScadOptions) -> String
+where
+    T: Real,
+{
+    let unit = options.unit_size;
+    let margin = options.plate_margin;
+    let cutout = fmt_num(options.cutout_size);
+
+    let mut result = String::new();
+    result.push_str("difference() {\n");
+
+    if let Some(bbox) = keyboard.bounding_box() {
+        let (x, y) = (
+            to_f64(bbox.x) * unit - margin,
+            to_f64(bbox.y) * unit - margin,
+        );
+        let (w, h) = (
+            to_f64(bbox.width) * unit + 2.0 * margin,
+            to_f64(bbox.height) * unit + 2.0 * margin,
+        );
+        let _ = writeln!(
+            result,
+            "    translate([{}, {}]) square([{}, {}]);",
+            fmt_num(x),
+            fmt_num(-(y + h)),
+            fmt_num(w),
+            fmt_num(h),
+        );
+    }
+
+    for key in keyboard.keys.iter().filter(|key| !key.decal) {
+        let (x, y) = key.center();
+        let _ = writeln!(
+            result,
+            "    translate([{}, {}]) rotate({}) square([{cutout}, {cutout}], center = true);",
+            fmt_num(to_f64(x) * unit),
+            fmt_num(-to_f64(y) * unit),
+            fmt_num(-to_f64(key.rotation)),
+        );
+    }
+
+    result.push_str("}\n");
+    result
+}
+
+// Quotes a string for use in a KiCad S-expression
+fn kicad_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Generates a [KiCad] PCB snippet placing a switch footprint on each key.
+///
+/// Each key is emitted as a `footprint` S-expression, centred on the key and rotated to match the
+/// key's rotation. The footprint, layer, and reference designators are configured using
+/// [`KicadOptions`]. Reference designators are written as a `property`, which requires `KiCad` 8
+/// or later.
+///
+/// Each footprint only contains its library identifier, position, and reference, not the
+/// footprint's pads or graphics. After inserting the snippet into a board's `.kicad_pcb` file, run
+/// *Update Footprints from Library* in the PCB editor to load the full footprints.
+///
+/// All dimensions are in millimetres. `KiCad`'s Y axis points down as in KLE, but its rotations
+/// are counterclockwise, so rotations are negated. Decals are not physical keys and do not get
+/// footprints, and are not counted when numbering the reference designators.
+///
+/// [KiCad]: https://www.kicad.org/
+#[must_use]
+pub fn to_kicad_pcb_snippet<T>(keyboard: &Keyboard<T>, options: &KicadOptions) -> String
+where
+    T: Real,
+{
+    let unit = options.unit_size;
+    let footprint = kicad_string(&options.footprint);
+    let layer = kicad_string(&options.layer);
+
+    let mut result = String::new();
+    for (i, key) in keyboard.keys.iter().filter(|key| !key.decal).enumerate() {
+        let (x, y) = key.center();
+        let reference = kicad_string(&format!("{}{}", options.reference_prefix, i + 1));
+        let _ = writeln!(
+            result,
+            "(footprint {footprint} (layer {layer}) (at {} {} {}) (property \"Reference\" {reference}))",
+            fmt_num(to_f64(x) * unit),
+            fmt_num(to_f64(y) * unit),
+            fmt_num(-to_f64(key.rotation)),
+        );
+    }
+
+    result
+}
+
+/// Generates a [Graphviz] DOT graph of the keyboard's rotation clusters.
+///
+/// Each cluster returned by [`Keyboard::rotation_clusters`] is a node labelled with its rotation,
+/// centre of rotation, and number of keys. Nodes are named `cluster0`, `cluster1`, and so on in
+/// the order the clusters first appear in the layout. The graph has no edges; it is intended as a
+/// quick overview of how a complex layout is divided into rotated sections.
+///
+/// The output can be rendered using Graphviz, for example with `dot -Tsvg`.
+///
+/// [Graphviz]: https://graphviz.org/
+#[must_use]
+pub fn clusters_to_dot<T>(keyboard: &Keyboard<T>) -> String
+where
+    T: Real,
+{
+    let mut result = String::new();
+    result.push_str("graph clusters {\n");
+    result.push_str("    node [shape=box];\n");
+
+    for (i, (params, keys)) in keyboard.rotation_clusters().iter().enumerate() {
+        let plural = if keys.len() == 1 { "" } else { "s" };
+        let _ = writeln!(
+            result,
+            "    cluster{i} [label=\"r = {}\\nrx = {}, ry = {}\\n{} key{plural}\"];",
+            fmt_num(to_f64(params.rotation)),
+            fmt_num(to_f64(params.rx)),
+            fmt_num(to_f64(params.ry)),
+            keys.len(),
+        );
+    }
+
+    result.push_str("}\n");
+    result
+}
+
+// Parses a `row,col` matrix position, allowing whitespace around each number
+fn parse_matrix_position(text: &str) -> Option<(usize, usize)> {
+    let (row, col) = text.split_once(',')?;
+    Some((row.trim().parse().ok()?, col.trim().parse().ok()?))
+}
+
+// Quotes a CSV field if it contains a delimiter, quote, or line break
+fn csv_field(text: &str) -> String {
+    if text.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.into()
+    }
+}
+
+/// Generates a CSV table of each key's position and switch matrix position.
+///
+/// The matrix position is read from the legend at index `slot` of each key, which must contain a
+/// `row,col` pair such as `0,3`, as is common for layouts annotated for firmware such as QMK.
+///
+/// The output has a header row followed by one row per key with the columns `x`, `y`, `row`,
+/// `col`, and `legend`. `x` and `y` are the coordinates of the key's
+/// [`centre`](crate::Key::center) in keyboard units, and `legend` is the key's first legend other
+/// than the matrix position, or empty if it has none. Decals are not physical keys and are
+/// skipped.
+///
+/// # Errors
+///
+/// Returns a [`MatrixError`] for the first key whose legend at index `slot` is missing or is not
+/// a valid `row,col` pair.
+pub fn to_matrix_csv<T>(keyboard: &Keyboard<T>, slot: usize) -> Result<String, MatrixError>
+where
+    T: Real,
+{
+    let mut result = String::from("x,y,row,col,legend\n");
+
+    for (index, key) in keyboard
+        .keys
+        .iter()
+        .enumerate()
+        .filter(|(_, key)| !key.decal)
+    {
+        let text = key
+            .legends
+            .get(slot)
+            .and_then(Option::as_ref)
+            .map(|legend| legend.text.as_str());
+        let (row, col) = match text.map(|text| (text, parse_matrix_position(text))) {
+            Some((_, Some(position))) => position,
+            Some((text, None)) => {
+                return Err(MatrixError {
+                    index,
+                    message: format!("legend {slot} ({text:?}) is not a `row,col` pair"),
+                })
+            }
+            None => {
+                return Err(MatrixError {
+                    index,
+                    message: format!("legend {slot} is missing"),
+                })
+            }
+        };
+
+        let legend = key
+            .legends_iter()
+            .find(|&(i, _)| i != slot)
+            .map_or("", |(_, legend)| legend.text.as_str());
+        let (x, y) = key.center();
+        let _ = writeln!(
+            result,
+            "{},{},{row},{col},{}",
+            fmt_num(to_f64(x)),
+            fmt_num(to_f64(y)),
+            csv_field(legend),
+        );
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fmt_num() {
+        assert_eq!(fmt_num(1.0), "1");
+        assert_eq!(fmt_num(19.05), "19.05");
+        assert_eq!(fmt_num(-0.00001), "0");
+        assert_eq!(fmt_num(1.0 / 3.0), "0.3333");
+    }
+
+    #[test]
+    fn test_to_openscad_plate() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", "B"],
+                [{"r": 15, "rx": 1, "ry": 1}, "C"],
+                [{"d": true}, "Decal"]
+            ]"#,
+        )
+        .unwrap();
+        let scad = to_openscad_plate(
+            &kb,
+            &OpenScadOptions {
+                unit_size: 20.0,
+                cutout_size: 14.0,
+                plate_margin: 5.0,
+            },
+        );
+        let lines: Vec<_> = scad.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "difference() {");
+        assert!(lines[1].starts_with("    translate([-5, "));
+        assert_eq!(
+            lines[2],
+            "    translate([10, -10]) rotate(0) square([14, 14], center = true);"
+        );
+        assert_eq!(
+            lines[3],
+            "    translate([30, -10]) rotate(0) square([14, 14], center = true);"
+        );
+        assert!(lines[4].contains("rotate(-15)"));
+        assert_eq!(lines[5], "}");
+
+        let scad = to_openscad_plate(&<Keyboard>::default(), &OpenScadOptions::default());
+        assert_eq!(scad, "difference() {\n}\n");
+    }
+
+    #[test]
+    fn test_kicad_string() {
+        assert_eq!(kicad_string("F.Cu"), r#""F.Cu""#);
+        assert_eq!(kicad_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+
+    #[test]
+    fn test_to_kicad_pcb_snippet() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", {"w": 2}, "B"],
+                [{"d": true}, "Decal"],
+                [{"r": 15, "rx": 1, "ry": 1}, "C"]
+            ]"#,
+        )
+        .unwrap();
+        let snippet = to_kicad_pcb_snippet(
+            &kb,
+            &KicadOptions {
+                unit_size: 20.0,
+                footprint: "Lib:Switch".into(),
+                layer: "B.Cu".into(),
+                reference_prefix: "K".into(),
+            },
+        );
+        let lines: Vec<_> = snippet.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            r#"(footprint "Lib:Switch" (layer "B.Cu") (at 10 10 0) (property "Reference" "K1"))"#
+        );
+        assert_eq!(
+            lines[1],
+            r#"(footprint "Lib:Switch" (layer "B.Cu") (at 40 10 0) (property "Reference" "K2"))"#
+        );
+        assert!(lines[2].contains(" -15) "));
+        assert!(lines[2].ends_with(r#"(property "Reference" "K3"))"#));
+
+        let snippet = to_kicad_pcb_snippet(&<Keyboard>::default(), &KicadOptions::default());
+        assert!(snippet.is_empty());
+    }
+
+    #[test]
+    fn test_clusters_to_dot() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", "B"],
+                [{"r": 15, "rx": 1, "ry": 2.5}, "C", "D", "E"],
+                [{"r": -30, "rx": 4}, "F"],
+                [{"r": 0, "rx": 0, "ry": 0}, "G"]
+            ]"#,
+        )
+        .unwrap();
+        let dot = clusters_to_dot(&kb);
+        let lines: Vec<_> = dot.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "graph clusters {");
+        assert_eq!(lines[1], "    node [shape=box];");
+        assert_eq!(
+            lines[2],
+            r#"    cluster0 [label="r = 0\nrx = 0, ry = 0\n3 keys"];"#
+        );
+        assert_eq!(
+            lines[3],
+            r#"    cluster1 [label="r = 15\nrx = 1, ry = 2.5\n3 keys"];"#
+        );
+        assert_eq!(
+            lines[4],
+            r#"    cluster2 [label="r = -30\nrx = 4, ry = 2.5\n1 key"];"#
+        );
+        assert_eq!(lines[5], "}");
+
+        let dot = clusters_to_dot(&<Keyboard>::default());
+        assert_eq!(dot, "graph clusters {\n    node [shape=box];\n}\n");
+    }
+
+    #[test]
+    fn test_to_matrix_csv() {
+        // A minimal CSV parser supporting quoted fields with escaped quotes
+        fn parse_line(line: &str) -> Vec<String> {
+            let (mut fields, mut field, mut quoted) = (Vec::new(), String::new(), false);
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted && chars.peek() == Some(&'"') => {
+                        field.push('"');
+                        chars.next();
+                    }
+                    '"' => quoted = !quoted,
+                    ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                    c => field.push(c),
+                }
+            }
+            fields.push(field);
+            fields
+        }
+
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                [{"a": 0}, "0,0\n\n\n\nEsc", "0, 1\n\n\n\n\"Q\", \"q\""],
+                [{"d": true}, "Decal"],
+                [{"w": 2}, "1,0"]
+            ]"#,
+        )
+        .unwrap();
+        let csv = to_matrix_csv(&kb, 0).unwrap();
+        let rows: Vec<_> = csv.lines().map(parse_line).collect();
+
+        assert_eq!(
+            rows,
+            [
+                ["x", "y", "row", "col", "legend"],
+                ["0.5", "0.5", "0", "0", "Esc"],
+                ["1.5", "0.5", "0", "1", r#""Q", "q""#],
+                ["1", "2.5", "1", "0", ""],
+            ]
+        );
+
+        let err = to_matrix_csv(&kb, 1).unwrap_err();
+        assert_eq!(err.index, 0);
+        assert_eq!(err.to_string(), "key 0: legend 1 is missing");
+
+        let kb: Keyboard = serde_json::from_str(r#"[["0,0", "A"]]"#).unwrap();
+        let err = to_matrix_csv(&kb, 0).unwrap_err();
+        assert_eq!(err.index, 1);
+        assert_eq!(
+            err.to_string(),
+            r#"key 1: legend 0 ("A") is not a `row,col` pair"#
+        );
+    }
+}
@@ -6,6 +6,12 @@ pub type Color = crate::Color;
 /// Type alias of [`crate::Legend`]
 pub type Legend = crate::Legend;
 
+/// Type alias of [`crate::TextRun`]
+pub type TextRun = crate::TextRun;
+
+/// Type alias of [`crate::TextStyle`]
+pub type TextStyle = crate::TextStyle;
+
 /// Type alias of [`crate::Switch`]
 pub type Switch = crate::Switch;
 
@@ -18,8 +24,23 @@ pub type Background = crate::Background;
 /// Type alias of [`crate::Metadata`]
 pub type Metadata = crate::Metadata;
 
+/// Type alias of [`crate::DeserializeOptions`]
+pub type DeserializeOptions = crate::DeserializeOptions;
+
+/// Type alias of [`crate::Rect<f32>`]
+pub type Rect = crate::Rect<f32>;
+
+/// Type alias of [`crate::RotationParams<f32>`]
+pub type RotationParams = crate::RotationParams<f32>;
+
 /// Type alias of [`crate::Keyboard<f32>`]
 pub type Keyboard = crate::Keyboard<f32>;
 
+/// Type alias of [`crate::AnalyzedKeyboard<f32>`]
+pub type AnalyzedKeyboard = crate::AnalyzedKeyboard<f32>;
+
+/// Type alias of [`crate::LayoutBuilder<f32>`]
+pub type LayoutBuilder = crate::LayoutBuilder<f32>;
+
 /// Type alias of [`crate::KeyIterator<f32>`]
 pub type KeyIterator = crate::KeyIterator<f32>;
@@ -0,0 +1,311 @@
+// A minimal fixed point type implementing Real, used to check we don't rely on any IEEE float
+// behaviour (such as NaN or infinities) anywhere in the crate
+
+use std::{
+    num::ParseFloatError,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+};
+
+use num_traits::{real::Real, Num, NumCast, One, ToPrimitive, Zero};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Key, KeyIterator, Keyboard};
+
+const FRAC_BITS: u32 = 32;
+#[allow(clippy::cast_precision_loss)] // Powers of two are exactly representable
+const SCALE: f64 = (1_u64 << FRAC_BITS) as f64;
+
+// Q31.32 fixed point
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Fixed(i64);
+
+impl Fixed {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from_f64(value: f64) -> Self {
+        Self((value * SCALE).round() as i64)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn as_f64(self) -> f64 {
+        self.0 as f64 / SCALE
+    }
+
+    fn approx_eq(self, other: f64) -> bool {
+        (self.as_f64() - other).abs() < 1e-6
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+    #[allow(clippy::cast_possible_truncation)]
+    fn mul(self, rhs: Self) -> Self {
+        let (a, b): (i128, i128) = (self.0.into(), rhs.0.into());
+        Self(((a * b) >> FRAC_BITS) as i64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+    #[allow(clippy::cast_possible_truncation)]
+    fn div(self, rhs: Self) -> Self {
+        let (a, b): (i128, i128) = (self.0.into(), rhs.0.into());
+        Self(((a << FRAC_BITS) / b) as i64)
+    }
+}
+
+impl Rem for Fixed {
+    type Output = Self;
+    fn rem(self, rhs: Self) -> Self {
+        Self(self.0 % rhs.0)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl Zero for Fixed {
+    fn zero() -> Self {
+        Self(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl One for Fixed {
+    fn one() -> Self {
+        Self(1 << FRAC_BITS)
+    }
+}
+
+impl Num for Fixed {
+    type FromStrRadixErr = ParseFloatError;
+
+    // Only used for radix 10 in practice
+    fn from_str_radix(str: &str, _radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        str.parse().map(Self::from_f64)
+    }
+}
+
+impl ToPrimitive for Fixed {
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.0 >> FRAC_BITS)
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.to_i64()?.try_into().ok()
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.as_f64())
+    }
+}
+
+impl NumCast for Fixed {
+    fn from<N: ToPrimitive>(n: N) -> Option<Self> {
+        n.to_f64()
+            .filter(|f| f.is_finite() && f.abs() < SCALE / 2.0)
+            .map(Self::from_f64)
+    }
+}
+
+macro_rules! via_f64 {
+    ($($name:ident),* $(,)?) => {
+        $(
+            fn $name(self) -> Self {
+                Self::from_f64(self.as_f64().$name())
+            }
+        )*
+    };
+    ($($name:ident($arg:ident)),* $(,)?) => {
+        $(
+            fn $name(self, $arg: Self) -> Self {
+                Self::from_f64(self.as_f64().$name($arg.as_f64()))
+            }
+        )*
+    };
+}
+
+impl Real for Fixed {
+    via_f64!(
+        floor, ceil, round, trunc, fract, recip, sqrt, exp, exp2, ln, log2, log10, to_degrees,
+        to_radians, cbrt, sin, cos, tan, asin, acos, atan, exp_m1, ln_1p, sinh, cosh, tanh, asinh,
+        acosh, atanh,
+    );
+    via_f64!(powf(n), log(base), hypot(other), atan2(other));
+
+    fn min_value() -> Self {
+        Self(i64::MIN)
+    }
+
+    fn min_positive_value() -> Self {
+        Self(1)
+    }
+
+    fn epsilon() -> Self {
+        Self(1)
+    }
+
+    fn max_value() -> Self {
+        Self(i64::MAX)
+    }
+
+    fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    fn signum(self) -> Self {
+        Self(self.0.signum() << FRAC_BITS)
+    }
+
+    fn is_sign_positive(self) -> bool {
+        self.0 >= 0
+    }
+
+    fn is_sign_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        self * a + b
+    }
+
+    fn powi(self, n: i32) -> Self {
+        Self::from_f64(self.as_f64().powi(n))
+    }
+
+    fn max(self, other: Self) -> Self {
+        Ord::max(self, other)
+    }
+
+    fn min(self, other: Self) -> Self {
+        Ord::min(self, other)
+    }
+
+    fn abs_sub(self, other: Self) -> Self {
+        Ord::max(self - other, Self::zero())
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        (self.sin(), self.cos())
+    }
+}
+
+impl<'de> Deserialize<'de> for Fixed {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        f64::deserialize(deserializer).map(Self::from_f64)
+    }
+}
+
+impl Serialize for Fixed {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.as_f64().serialize(serializer)
+    }
+}
+
+const LAYOUT: &str = r#"[
+    {"name": "fixed"},
+    ["A", {"w": 1.5}, "B", {"x": 0.25}, "C"],
+    [{"y": 0.5, "w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "D", {"d": true}, "E"],
+    [{"r": 30, "rx": 4, "ry": 1}, "F", "G"],
+    [{"f": 12, "fa": [0, 4]}, "H\nI"]
+]"#;
+
+#[test]
+fn test_fixed_deserialize() {
+    let kb: Keyboard<Fixed> = serde_json::from_str(LAYOUT).unwrap();
+
+    assert_eq!(kb.keys.len(), 8);
+    assert!(kb.keys[1].x.approx_eq(1.0));
+    assert!(kb.keys[2].x.approx_eq(2.75));
+    assert!(kb.keys[3].y.approx_eq(1.5));
+    assert!(kb.keys[3].x2.approx_eq(-0.25));
+    assert!(kb.keys[5].rotation.approx_eq(30.0));
+    assert!(kb.keys[6].x.approx_eq(5.0));
+    assert_eq!(kb.keys[7].legends[0].as_ref().unwrap().size, 9);
+    assert_eq!(kb.keys[7].legends[6].as_ref().unwrap().size, 4);
+
+    let keys: Vec<Key<Fixed>> = serde_json::from_str::<KeyIterator<Fixed>>(LAYOUT)
+        .unwrap()
+        .collect();
+    assert_eq!(keys, kb.keys);
+}
+
+#[test]
+fn test_fixed_geometry() {
+    let kb: Keyboard<Fixed> = serde_json::from_str(LAYOUT).unwrap();
+
+    let (x, y) = kb.keys[0].center();
+    assert!(x.approx_eq(0.5));
+    assert!(y.approx_eq(0.5));
+
+    // The rotated keys' centres match those calculated using f64
+    let reference: Keyboard<f64> = serde_json::from_str(LAYOUT).unwrap();
+    for (key, reference) in kb.keys.iter().zip(&reference.keys) {
+        let ((x, y), (rx, ry)) = (key.center(), reference.center());
+        assert!(x.approx_eq(rx));
+        assert!(y.approx_eq(ry));
+    }
+
+    let bbox = kb.bounding_box().unwrap();
+    let expected = reference.bounding_box().unwrap();
+    assert!(bbox.x.approx_eq(expected.x));
+    assert!(bbox.y.approx_eq(expected.y));
+    assert!(bbox.width.approx_eq(expected.width));
+    assert!(bbox.height.approx_eq(expected.height));
+
+    let clusters = kb.rotation_clusters();
+    assert_eq!(clusters.len(), 2);
+    assert_eq!(clusters[1].1, [5, 6, 7]);
+
+    assert!(!kb.keys[0].overlaps(&kb.keys[1], Fixed::zero()));
+    assert!(kb.keys[3].overlaps(&kb.keys[3], Fixed::zero()));
+
+    let (index, _) = kb
+        .nearest_key(Fixed::from_f64(1.6), Fixed::from_f64(0.4))
+        .unwrap();
+    assert_eq!(index, 1);
+
+    let mut shifted = kb.clone();
+    shifted.shift_rotation_origins(Fixed::one(), Fixed::one());
+    for (key, shifted) in kb.keys.iter().zip(&shifted.keys) {
+        let ((x, y), (sx, sy)) = (key.center(), shifted.center());
+        assert!(x.approx_eq(sx.as_f64()));
+        assert!(y.approx_eq(sy.as_f64()));
+    }
+}
+
+#[test]
+fn test_fixed_serialize_round_trip() {
+    let kb: Keyboard<Fixed> = serde_json::from_str(LAYOUT).unwrap();
+
+    let json = serde_json::to_string(&kb).unwrap();
+    let round_trip: Keyboard<Fixed> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(round_trip, kb);
+}
@@ -0,0 +1,1093 @@
+use std::cmp::Ordering;
+
+use num_traits::real::Real;
+
+use crate::{utils::real, Key, Keyboard};
+
+// Tolerance used when comparing rotation parameters
+const ROTATION_TOLERANCE: f64 = 1e-6;
+
+// Tolerance used when checking whether a key is contained in a rectangle, so keys exactly on the
+// edge aren't reported due to floating point error in their rotated corners
+const CONTAINMENT_TOLERANCE: f64 = 1e-6;
+
+/// An axis-aligned rectangle measured in keyboard units (typically 19.05 mm or 0.75 in).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect<T = f64>
+where
+    T: Real,
+{
+    /// The X position of the rectangle's top left corner.
+    pub x: T,
+    /// The Y position of the rectangle's top left corner.
+    pub y: T,
+    /// The width of the rectangle.
+    pub width: T,
+    /// The height of the rectangle.
+    pub height: T,
+}
+
+impl<T> Rect<T>
+where
+    T: Real,
+{
+    /// Returns the smallest rectangle containing all of the given points, or [`None`] if there are
+    /// no points.
+    pub(crate) fn from_points<I>(points: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = (T, T)>,
+    {
+        let mut points = points.into_iter();
+        let (x, y) = points.next()?;
+
+        let (x_min, y_min, x_max, y_max) =
+            points.fold((x, y, x, y), |(x_min, y_min, x_max, y_max), (x, y)| {
+                (x_min.min(x), y_min.min(y), x_max.max(x), y_max.max(y))
+            });
+
+        Some(Self {
+            x: x_min,
+            y: y_min,
+            width: x_max - x_min,
+            height: y_max - y_min,
+        })
+    }
+}
+
+/// The rotation parameters of a key. Keys sharing the same rotation parameters form a rotation
+/// cluster.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RotationParams<T = f64>
+where
+    T: Real,
+{
+    /// The rotation in degrees. Positive rotation values are clockwise.
+    pub rotation: T,
+    /// The X coordinate for the centre of rotation.
+    pub rx: T,
+    /// The Y coordinate for the centre of rotation.
+    pub ry: T,
+}
+
+impl<T> RotationParams<T>
+where
+    T: Real,
+{
+    pub(crate) fn approx_eq(&self, other: &Self) -> bool {
+        let tol = real(ROTATION_TOLERANCE);
+        (self.rotation - other.rotation).abs() <= tol
+            && (self.rx - other.rx).abs() <= tol
+            && (self.ry - other.ry).abs() <= tol
+    }
+}
+
+impl<T> Key<T>
+where
+    T: Real,
+{
+    /// Returns the key's rotation parameters.
+    #[must_use]
+    pub fn rotation_params(&self) -> RotationParams<T> {
+        RotationParams {
+            rotation: self.rotation,
+            rx: self.rx,
+            ry: self.ry,
+        }
+    }
+
+    /// Transforms a point from the key's unrotated coordinate space to the layout's coordinate
+    /// space, by rotating it by [`Key::rotation`] degrees clockwise about ([`Key::rx`],
+    /// [`Key::ry`]).
+    #[must_use]
+    pub fn rotate_point(&self, x: T, y: T) -> (T, T) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (dx, dy) = (x - self.rx, y - self.ry);
+
+        (self.rx + dx * cos - dy * sin, self.ry + dx * sin + dy * cos)
+    }
+
+    /// Returns the centre of the key's primary rectangle in the layout's coordinate space, taking
+    /// the key's rotation into account.
+    #[must_use]
+    pub fn center(&self) -> (T, T) {
+        let two = T::one() + T::one();
+        self.rotate_point(self.x + self.width / two, self.y + self.height / two)
+    }
+
+    /// Returns the corners of the key's primary rectangle in the layout's coordinate space, taking
+    /// the key's rotation into account.
+    ///
+    /// The corners are in clockwise order starting from the top left corner of the unrotated key.
+    #[must_use]
+    pub fn corners(&self) -> [(T, T); 4] {
+        self.rect_corners(self.x, self.y, self.width, self.height)
+    }
+
+    /// Returns the corners of the key's secondary rectangle in the layout's coordinate space,
+    /// taking the key's rotation into account.
+    ///
+    /// The corners are ordered the same as in [`Key::corners`]. For regular keys, the secondary
+    /// rectangle is the same as the primary rectangle.
+    #[must_use]
+    pub fn corners2(&self) -> [(T, T); 4] {
+        self.rect_corners(
+            self.x + self.x2,
+            self.y + self.y2,
+            self.width2,
+            self.height2,
+        )
+    }
+
+    /// Returns the axis-aligned bounding box of the key in the layout's coordinate space, taking
+    /// rotation and stepped or L-shaped keys into account.
+    ///
+    /// This is cheaper to test against than the key's corners, so is useful as a quick check
+    /// before using more precise methods such as [`Key::overlaps`].
+    #[must_use]
+    pub fn rotated_bounds(&self) -> Rect<T> {
+        Rect::from_points(self.corners().into_iter().chain(self.corners2()))
+            .unwrap_or_else(|| unreachable!("a key always has corners"))
+    }
+
+    /// Returns whether the key overlaps another key, taking rotation and stepped or L-shaped keys
+    /// into account.
+    ///
+    /// Each key is treated as the union of its primary and secondary rectangles, and each pair of
+    /// rectangles is checked using the separating axis theorem: two convex polygons are disjoint
+    /// if and only if their projections onto one of the polygons' edge normals do not overlap.
+    ///
+    /// Keys are only considered to overlap if the overlap along every axis is greater than `tol`
+    /// keyboard units. A `tol` of zero means keys which are exactly touching do not overlap, while
+    /// a small positive value can be used to allow for floating point error or slightly misaligned
+    /// keys.
+    #[must_use]
+    pub fn overlaps(&self, other: &Self, tol: T) -> bool {
+        let rects = [self.corners(), self.corners2()];
+        let others = [other.corners(), other.corners2()];
+
+        rects
+            .iter()
+            .any(|a| others.iter().any(|b| polygons_overlap(a, b, tol)))
+    }
+
+    /// Returns the shortest distance between the edges of the key and another key, taking rotation
+    /// and stepped or L-shaped keys into account. Returns zero if the keys touch or overlap.
+    #[must_use]
+    pub fn distance_to(&self, other: &Self) -> T {
+        let rects = [self.corners(), self.corners2()];
+        let others = [other.corners(), other.corners2()];
+
+        (rects.iter())
+            .flat_map(|a| others.iter().map(move |b| polygon_distance(a, b)))
+            .fold(T::max_value(), T::min)
+    }
+
+    fn rect_corners(&self, x: T, y: T, width: T, height: T) -> [(T, T); 4] {
+        [
+            self.rotate_point(x, y),
+            self.rotate_point(x + width, y),
+            self.rotate_point(x + width, y + height),
+            self.rotate_point(x, y + height),
+        ]
+    }
+}
+
+// Checks for overlap between two convex polygons using the separating axis theorem
+fn polygons_overlap<T>(a: &[(T, T); 4], b: &[(T, T); 4], tol: T) -> bool
+where
+    T: Real,
+{
+    let project = |poly: &[(T, T); 4], (ax, ay): (T, T)| {
+        poly.iter()
+            .map(|&(x, y)| x * ax + y * ay)
+            .fold((T::max_value(), T::min_value()), |(min, max), p| {
+                (min.min(p), max.max(p))
+            })
+    };
+
+    [a, b].into_iter().all(|poly| {
+        (0..poly.len()).all(|i| {
+            let ((x1, y1), (x2, y2)) = (poly[i], poly[(i + 1) % poly.len()]);
+            let (nx, ny) = (y1 - y2, x2 - x1);
+            let len = nx.hypot(ny);
+            if len.is_zero() {
+                // Degenerate edge, so there's no axis to check
+                return true;
+            }
+
+            let axis = (nx / len, ny / len);
+            let ((a_min, a_max), (b_min, b_max)) = (project(a, axis), project(b, axis));
+            a_max.min(b_max) - a_min.max(b_min) > tol
+        })
+    })
+}
+
+// Returns the distance between a point and a line segment
+fn point_segment_distance<T>((px, py): (T, T), (ax, ay): (T, T), (bx, by): (T, T)) -> T
+where
+    T: Real,
+{
+    let (dx, dy) = (bx - ax, by - ay);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq.is_zero() {
+        T::zero()
+    } else {
+        ((px - ax) * dx + (py - ay) * dy) / len_sq
+    };
+    let t = t.max(T::zero()).min(T::one());
+
+    (px - (ax + t * dx)).hypot(py - (ay + t * dy))
+}
+
+// Returns the convex hull of the given points in counterclockwise order (in a Y-up coordinate
+// space) using Andrew's monotone chain algorithm. Collinear points are omitted
+fn convex_hull<T>(mut points: Vec<(T, T)>) -> Vec<(T, T)>
+where
+    T: Real,
+{
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |(ox, oy): (T, T), (ax, ay): (T, T), (bx, by): (T, T)| {
+        (ax - ox) * (by - oy) - (ay - oy) * (bx - ox)
+    };
+    let half_hull = |points: &mut dyn Iterator<Item = (T, T)>| {
+        let mut hull: Vec<(T, T)> = Vec::new();
+        for point in points {
+            while hull.len() >= 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= T::zero()
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point is the first point of the other half
+        hull.pop();
+        hull
+    };
+
+    let mut hull = half_hull(&mut points.iter().copied());
+    hull.extend(half_hull(&mut points.iter().rev().copied()));
+    hull
+}
+
+// Returns the distance between two convex polygons, or zero if they overlap
+fn polygon_distance<T>(a: &[(T, T); 4], b: &[(T, T); 4]) -> T
+where
+    T: Real,
+{
+    if polygons_overlap(a, b, T::zero()) {
+        return T::zero();
+    }
+
+    // If the polygons don't overlap, the shortest distance is always between a vertex of one
+    // polygon and an edge of the other
+    let edges = |poly: &[(T, T); 4]| -> [((T, T), (T, T)); 4] {
+        std::array::from_fn(|i| (poly[i], poly[(i + 1) % poly.len()]))
+    };
+    let vertex_edge = |points: &[(T, T); 4], poly: &[(T, T); 4]| {
+        let edges = edges(poly);
+        points
+            .iter()
+            .flat_map(|&p| {
+                edges
+                    .iter()
+                    .map(move |&(a, b)| point_segment_distance(p, a, b))
+            })
+            .fold(T::max_value(), T::min)
+    };
+
+    vertex_edge(a, b).min(vertex_edge(b, a))
+}
+
+impl<T> Keyboard<T>
+where
+    T: Real,
+{
+    /// Returns the indices of all keys within `gap_tol` keyboard units of the key at `index`,
+    /// measured edge to edge and taking rotation and stepped or L-shaped keys into account.
+    ///
+    /// Keys that touch or overlap the given key are always included. The key itself is not
+    /// included. Returns an empty [`Vec`] if `index` is out of bounds.
+    #[must_use]
+    pub fn adjacent_keys(&self, index: usize, gap_tol: T) -> Vec<usize> {
+        self.keys
+            .get(index)
+            .map(|key| {
+                (self.keys.iter().enumerate())
+                    .filter(|&(i, other)| i != index && key.distance_to(other) <= gap_tol)
+                    .map(|(i, _)| i)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the axis-aligned bounding box of all keys in the layout, taking rotation and
+    /// stepped or L-shaped keys into account.
+    ///
+    /// Decals are not physical keys and are excluded by default; use
+    /// [`Keyboard::bounding_box_including_decals`] to include them. Returns [`None`] if the layout
+    /// has no non-decal keys.
+    #[must_use]
+    pub fn bounding_box(&self) -> Option<Rect<T>> {
+        self.bounding_box_impl(false)
+    }
+
+    /// Returns the axis-aligned bounding box of all keys in the layout including decals.
+    ///
+    /// This is the same as [`Keyboard::bounding_box`], but decals are included. Returns [`None`]
+    /// if the layout has no keys.
+    #[must_use]
+    pub fn bounding_box_including_decals(&self) -> Option<Rect<T>> {
+        self.bounding_box_impl(true)
+    }
+
+    fn bounding_box_impl(&self, include_decals: bool) -> Option<Rect<T>> {
+        Rect::from_points(
+            self.keys
+                .iter()
+                .filter(|key| include_decals || !key.decal)
+                .flat_map(|key| key.corners().into_iter().chain(key.corners2())),
+        )
+    }
+
+    /// Returns the indices of all keys which are not fully contained within `rect`, taking rotation
+    /// and stepped or L-shaped keys into account.
+    ///
+    /// This is useful for checking whether a layout fits within a case or plate of a given size.
+    /// Keys exactly touching the edge of `rect` are considered to be contained. Decals are not
+    /// physical keys and are never included.
+    #[must_use]
+    pub fn keys_outside(&self, rect: Rect<T>) -> Vec<usize> {
+        let tol = real(CONTAINMENT_TOLERANCE);
+        let contains = |(x, y): (T, T)| {
+            x >= rect.x - tol
+                && y >= rect.y - tol
+                && x <= rect.x + rect.width + tol
+                && y <= rect.y + rect.height + tol
+        };
+
+        (self.keys.iter().enumerate())
+            .filter(|(_, key)| !key.decal)
+            .filter(|(_, key)| {
+                !key.corners()
+                    .into_iter()
+                    .chain(key.corners2())
+                    .all(contains)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Returns the minimum area rectangle enclosing all keys in the layout, which may be rotated
+    /// unlike [`Keyboard::bounding_box`]. This takes rotation and stepped or L-shaped keys into
+    /// account.
+    ///
+    /// The result is a `(rotation, rect)` pair of the rectangle's rotation in degrees along with
+    /// its position and size in a coordinate space rotated by that angle about the origin. That is,
+    /// a [`Key`] with the same `x`, `y`, `width`, `height`, and `rotation`, and with `rx` and `ry`
+    /// set to zero, would exactly cover the rectangle. The rotation is in the range [-45, 45), and
+    /// is zero whenever an unrotated rectangle is no larger than any rotated one.
+    ///
+    /// The minimum area rectangle always has one side collinear with an edge of the convex hull of
+    /// the keys' corners, so each edge of the hull is tried in turn. Each attempt projects every
+    /// vertex of the hull, so for a hull with `h` vertices this takes O(h²) time rather than the
+    /// O(h) of rotating calipers. Since a hull rarely has more than a few dozen vertices even for
+    /// large layouts, this is not a problem in practice. Decals are not physical keys and are
+    /// excluded. Returns [`None`] if the layout has no non-decal keys.
+    #[must_use]
+    pub fn oriented_bounding_box(&self) -> Option<(T, Rect<T>)> {
+        let hull = convex_hull(
+            (self.keys.iter())
+                .filter(|key| !key.decal)
+                .flat_map(|key| key.corners().into_iter().chain(key.corners2()))
+                .collect(),
+        );
+
+        // The rectangle enclosing the hull in a coordinate space rotated by the given angle
+        let enclosing_rect = |angle: T| {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let rect = Rect::from_points(
+                (hull.iter()).map(|&(x, y)| (x * cos + y * sin, y * cos - x * sin)),
+            )?;
+            Some((angle, rect))
+        };
+
+        let quarter_turn = real::<T>(90.0);
+        let half_quarter = real::<T>(45.0);
+        let edge_angles = (0..hull.len()).map(|i| {
+            let ((x1, y1), (x2, y2)) = (hull[i], hull[(i + 1) % hull.len()]);
+            let angle = (y2 - y1).atan2(x2 - x1).to_degrees();
+            // Rectangles are symmetric under quarter turns, so normalise to [-45, 45)
+            angle - ((angle + half_quarter) / quarter_turn).floor() * quarter_turn
+        });
+
+        std::iter::once(T::zero())
+            .chain(edge_angles)
+            .filter_map(enclosing_rect)
+            .fold(
+                None,
+                |best: Option<(T, Rect<T>)>, (angle, rect)| match best {
+                    Some((_, best_rect))
+                        if best_rect.width * best_rect.height <= rect.width * rect.height =>
+                    {
+                        best
+                    }
+                    _ => Some((angle, rect)),
+                },
+            )
+    }
+
+    /// Returns the index of the key whose centre is nearest to the point (`x`, `y`), along with the
+    /// distance between the point and the key's centre.
+    ///
+    /// Key centres are calculated using [`Key::center`], so rotation is taken into account. Decals
+    /// are excluded; use [`Keyboard::nearest_key_including_decals`] to include them. If multiple
+    /// keys are equally near, the first is returned. Returns [`None`] if the layout has no
+    /// non-decal keys.
+    #[must_use]
+    pub fn nearest_key(&self, x: T, y: T) -> Option<(usize, T)> {
+        self.nearest_key_impl(x, y, false)
+    }
+
+    /// Returns the index of the key whose centre is nearest to the point (`x`, `y`), along with the
+    /// distance between the point and the key's centre.
+    ///
+    /// This is the same as [`Keyboard::nearest_key`], but decals are included.
+    #[must_use]
+    pub fn nearest_key_including_decals(&self, x: T, y: T) -> Option<(usize, T)> {
+        self.nearest_key_impl(x, y, true)
+    }
+
+    fn nearest_key_impl(&self, x: T, y: T, include_decals: bool) -> Option<(usize, T)> {
+        (self.keys.iter().enumerate())
+            .filter(|(_, key)| include_decals || !key.decal)
+            .map(|(i, key)| {
+                let (cx, cy) = key.center();
+                (i, (cx - x).hypot(cy - y))
+            })
+            .fold(None, |nearest, (i, dist)| match nearest {
+                Some((_, nearest_dist)) if nearest_dist <= dist => nearest,
+                _ => Some((i, dist)),
+            })
+    }
+
+    /// Moves the rotation origin of every key by (`dx`, `dy`), adjusting each key's position so it
+    /// stays in the same place in the layout's coordinate space.
+    ///
+    /// Since rotated keys are positioned relative to their unrotated coordinates, moving the
+    /// rotation origin also moves the key. To compensate, each key's unrotated position is offset
+    /// by the difference between the shift and the shift rotated back into the key's unrotated
+    /// coordinate space. Keys without rotation are therefore not moved at all.
+    pub fn shift_rotation_origins(&mut self, dx: T, dy: T) {
+        for key in &mut self.keys {
+            // Rotate the shift by -rotation into the key's unrotated coordinate space
+            let (sin, cos) = key.rotation.to_radians().sin_cos();
+            let (ux, uy) = (dx * cos + dy * sin, dy * cos - dx * sin);
+
+            key.x = key.x + dx - ux;
+            key.y = key.y + dy - uy;
+            key.rx = key.rx + dx;
+            key.ry = key.ry + dy;
+        }
+    }
+
+    /// Groups the layout's keys into rotation clusters, returning each cluster's rotation
+    /// parameters along with the indices of its keys.
+    ///
+    /// Keys are in the same cluster if their rotation, `rx`, and `ry` are all equal (within a small
+    /// tolerance to allow for floating point error). Clusters are returned in the order they first
+    /// appear in the layout.
+    #[must_use]
+    pub fn rotation_clusters(&self) -> Vec<(RotationParams<T>, Vec<usize>)> {
+        let mut clusters: Vec<(RotationParams<T>, Vec<usize>)> = Vec::new();
+
+        for (i, key) in self.keys.iter().enumerate() {
+            let params = key.rotation_params();
+            match clusters.iter_mut().find(|(p, _)| p.approx_eq(&params)) {
+                Some((_, indices)) => indices.push(i),
+                None => clusters.push((params, vec![i])),
+            }
+        }
+
+        clusters
+    }
+
+    /// Returns each rotation cluster's keys in the cluster's local coordinate space, along with
+    /// the cluster's rotation parameters and the keys' indices in [`Keyboard::keys`].
+    ///
+    /// In the local coordinate space the centre of rotation is at the origin and the keys are not
+    /// rotated. That is, each key's `x` and `y` are relative to the cluster's `rx` and `ry`, and
+    /// its `rotation`, `rx`, and `ry` are zero. A key's position in the layout can be recovered by
+    /// rotating its local position by the cluster's rotation about the origin and then offsetting
+    /// it by (`rx`, `ry`). This allows a renderer to draw each cluster's keys unrotated and apply a
+    /// single transform to the whole cluster.
+    ///
+    /// Clusters are determined in the same way as [`Keyboard::rotation_clusters`].
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn cluster_local_positions(&self) -> Vec<(RotationParams<T>, Vec<(usize, Key<T>)>)> {
+        self.rotation_clusters()
+            .into_iter()
+            .map(|(params, indices)| {
+                let keys = indices
+                    .into_iter()
+                    .map(|i| {
+                        let key = &self.keys[i];
+                        let local = Key {
+                            x: key.x - params.rx,
+                            y: key.y - params.ry,
+                            rotation: T::zero(),
+                            rx: T::zero(),
+                            ry: T::zero(),
+                            ..key.clone()
+                        };
+                        (i, local)
+                    })
+                    .collect();
+                (params, keys)
+            })
+            .collect()
+    }
+
+    /// Returns the indices of all keys in the same rotation cluster as the key at `index`,
+    /// including `index` itself.
+    ///
+    /// See [`Keyboard::rotation_clusters`] for how clusters are determined. Returns an empty
+    /// [`Vec`] if `index` is out of bounds.
+    #[must_use]
+    pub fn cluster_of(&self, index: usize) -> Vec<usize> {
+        self.keys
+            .get(index)
+            .map(|key| {
+                let params = key.rotation_params();
+                (self.keys.iter().enumerate())
+                    .filter(|(_, key)| key.rotation_params().approx_eq(&params))
+                    .map(|(i, _)| i)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use isclose::assert_is_close;
+
+    use super::*;
+
+    #[test]
+    fn test_rect_from_points() {
+        let rect = Rect::from_points([(1.0, 2.0), (-1.0, 0.5), (3.0, 1.0)]).unwrap();
+
+        assert_is_close!(rect.x, -1.0);
+        assert_is_close!(rect.y, 0.5);
+        assert_is_close!(rect.width, 4.0);
+        assert_is_close!(rect.height, 1.5);
+
+        assert!(Rect::<f64>::from_points([]).is_none());
+    }
+
+    #[test]
+    fn test_key_rotate_point() {
+        let key = Key {
+            rotation: 90.0,
+            rx: 1.0,
+            ry: 1.0,
+            ..Key::default()
+        };
+
+        let (x, y) = key.rotate_point(2.0, 1.0);
+        assert_is_close!(x, 1.0);
+        assert_is_close!(y, 2.0);
+
+        let (x, y) = <Key>::default().rotate_point(2.0, 1.0);
+        assert_is_close!(x, 2.0);
+        assert_is_close!(y, 1.0);
+    }
+
+    #[test]
+    fn test_key_center() {
+        let key = Key {
+            x: 1.0,
+            width: 2.0,
+            ..Key::default()
+        };
+        let (x, y) = key.center();
+        assert_is_close!(x, 2.0);
+        assert_is_close!(y, 0.5);
+
+        let key = Key {
+            width: 2.0,
+            rotation: 90.0,
+            ..Key::default()
+        };
+        let (x, y) = key.center();
+        assert_is_close!(x, -0.5);
+        assert_is_close!(y, 1.0);
+    }
+
+    #[test]
+    fn test_key_corners() {
+        let key = Key {
+            x: 1.0,
+            y: 2.0,
+            width: 1.5,
+            width2: 1.5,
+            ..Key::default()
+        };
+
+        let expected = [(1.0, 2.0), (2.5, 2.0), (2.5, 3.0), (1.0, 3.0)];
+        for ((x, y), (ex, ey)) in key.corners().into_iter().zip(expected) {
+            assert_is_close!(x, ex);
+            assert_is_close!(y, ey);
+        }
+        for ((x, y), (ex, ey)) in key.corners2().into_iter().zip(expected) {
+            assert_is_close!(x, ex);
+            assert_is_close!(y, ey);
+        }
+    }
+
+    #[test]
+    fn test_key_corners_rotated_iso_enter() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[[{"r": 90, "x": 0.25, "w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "Enter"]]"#,
+        )
+        .unwrap();
+        let key = &kb.keys[0];
+
+        let expected = [(0.0, 0.25), (0.0, 1.5), (-2.0, 1.5), (-2.0, 0.25)];
+        for ((x, y), (ex, ey)) in key.corners().into_iter().zip(expected) {
+            assert_is_close!(x, ex);
+            assert_is_close!(y, ey);
+        }
+
+        // The secondary rectangle must be rotated about the same origin as the primary
+        let expected = [(0.0, 0.0), (0.0, 1.5), (-1.0, 1.5), (-1.0, 0.0)];
+        for ((x, y), (ex, ey)) in key.corners2().into_iter().zip(expected) {
+            assert_is_close!(x, ex);
+            assert_is_close!(y, ey);
+        }
+
+        let bbox = kb.bounding_box().unwrap();
+        assert_is_close!(bbox.x, -2.0);
+        assert_is_close!(bbox.y, 0.0);
+        assert_is_close!(bbox.width, 2.0);
+        assert_is_close!(bbox.height, 1.5);
+    }
+
+    #[test]
+    fn test_key_rotated_bounds() {
+        let key = Key {
+            x: 1.0,
+            y: 2.0,
+            width: 2.0,
+            width2: 2.0,
+            ..Key::default()
+        };
+        assert_eq!(
+            key.rotated_bounds(),
+            Rect {
+                x: 1.0,
+                y: 2.0,
+                width: 2.0,
+                height: 1.0
+            }
+        );
+
+        // Rotating by 45 degrees around the centre enlarges the bounding box
+        let key = Key {
+            rotation: 45.0,
+            rx: 0.5,
+            ry: 0.5,
+            ..Key::default()
+        };
+        let bounds = key.rotated_bounds();
+        let half_diag = 0.5 * 2.0_f64.sqrt();
+        assert_is_close!(bounds.x, 0.5 - half_diag);
+        assert_is_close!(bounds.y, 0.5 - half_diag);
+        assert_is_close!(bounds.width, 2.0 * half_diag);
+        assert_is_close!(bounds.height, 2.0 * half_diag);
+
+        // Includes the secondary rectangle of an ISO enter
+        let key = Key {
+            x: 0.25,
+            width: 1.25,
+            height: 2.0,
+            x2: -0.25,
+            width2: 1.5,
+            height2: 1.0,
+            ..Key::default()
+        };
+        let bounds = key.rotated_bounds();
+        assert_is_close!(bounds.x, 0.0);
+        assert_is_close!(bounds.width, 1.5);
+        assert_is_close!(bounds.height, 2.0);
+    }
+
+    #[test]
+    fn test_key_overlaps() {
+        let key = Key {
+            width2: 1.0,
+            height2: 1.0,
+            ..Key::default()
+        };
+        let at = |x, y| Key {
+            x,
+            y,
+            ..key.clone()
+        };
+
+        // Overlapping
+        assert!(key.overlaps(&at(0.5, 0.5), 0.0));
+        assert!(key.overlaps(&key, 0.0));
+
+        // Touching
+        assert!(!key.overlaps(&at(1.0, 0.0), 0.0));
+        assert!(!key.overlaps(&at(1.0, 1.0), 0.0));
+
+        // Disjoint
+        assert!(!key.overlaps(&at(2.0, 0.0), 0.0));
+
+        // The tolerance allows for small overlaps
+        assert!(key.overlaps(&at(0.99, 0.0), 0.0));
+        assert!(!key.overlaps(&at(0.99, 0.0), 0.05));
+
+        // The bounding boxes overlap, but the rotated key does not
+        let rotated = |offset| Key {
+            rotation: 45.0,
+            rx: offset + 0.5,
+            ry: offset + 0.5,
+            ..at(offset, offset)
+        };
+        assert!(!key.overlaps(&rotated(1.1), 0.0));
+        assert!(key.overlaps(&rotated(0.7), 0.0));
+    }
+
+    #[test]
+    fn test_key_overlaps_iso_enter() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                [{"x": 0.25, "w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "Enter"],
+                [{"w": 0.25}, "A", "B"]
+            ]"#,
+        )
+        .unwrap();
+        let (enter, a, b) = (&kb.keys[0], &kb.keys[1], &kb.keys[2]);
+
+        // A fits under the enter key's overhang, while B overlaps the bottom of the enter key
+        assert!(!enter.overlaps(a, 0.0));
+        assert!(enter.overlaps(b, 0.0));
+        assert!(b.overlaps(enter, 0.0));
+    }
+
+    #[test]
+    fn test_key_distance_to() {
+        let key = <Key>::default();
+        let at = |x, y| Key {
+            x,
+            y,
+            ..key.clone()
+        };
+
+        assert_is_close!(key.distance_to(&at(0.5, 0.5)), 0.0);
+        assert_is_close!(key.distance_to(&at(1.0, 0.0)), 0.0);
+        assert_is_close!(key.distance_to(&at(1.5, 0.0)), 0.5);
+        assert_is_close!(key.distance_to(&at(2.0, 3.0)), 1.0_f64.hypot(2.0));
+        assert_is_close!(key.distance_to(&at(2.0, 2.0)), 1.0_f64.hypot(1.0));
+    }
+
+    #[test]
+    fn test_keyboard_adjacent_keys() {
+        // A 3x3 grid with 0.25u gaps between keys, so the diagonal neighbours are further away
+        // than the orthogonal ones
+        let rows = r#"
+            ["0", {"x": 0.25}, "1", {"x": 0.25}, "2"],
+            [{"y": 0.25}, "3", {"x": 0.25}, "4", {"x": 0.25}, "5"],
+            [{"y": 0.25}, "6", {"x": 0.25}, "7", {"x": 0.25}, "8"]
+        "#;
+        let grid = format!("[{rows}]");
+        let rotated = format!(r#"[[{{"r": 30, "rx": 1, "ry": 2}}], {rows}]"#);
+
+        for (json, rotation) in [(grid, 0.0), (rotated, 30.0)] {
+            let kb: Keyboard = serde_json::from_str(&json).unwrap();
+            assert_eq!(kb.keys.len(), 9);
+            assert_is_close!(kb.keys[8].rotation, rotation);
+
+            assert_eq!(kb.adjacent_keys(4, 0.3), [1, 3, 5, 7]);
+            assert_eq!(kb.adjacent_keys(0, 0.3), [1, 3]);
+            assert_eq!(kb.adjacent_keys(5, 0.3), [2, 4, 8]);
+            assert!(kb.adjacent_keys(4, 0.2).is_empty());
+            assert_eq!(kb.adjacent_keys(4, 0.4).len(), 8);
+            assert!(kb.adjacent_keys(9, 0.3).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_keyboard_rotation_clusters() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", "B"],
+                [{"r": 15, "rx": 1, "ry": 2}, "C", "D"],
+                [{"r": -15, "rx": 5}, "E"],
+                ["F"],
+                [{"r": 15, "rx": 1, "ry": 2}, "G"]
+            ]"#,
+        )
+        .unwrap();
+
+        let clusters = kb.rotation_clusters();
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters[0].1, [0, 1]);
+        assert_eq!(clusters[1].1, [2, 3, 6]);
+        assert_eq!(clusters[2].1, [4, 5]);
+        assert_is_close!(clusters[1].0.rotation, 15.0);
+        assert_is_close!(clusters[1].0.rx, 1.0);
+        assert_is_close!(clusters[1].0.ry, 2.0);
+
+        assert_eq!(kb.cluster_of(3), [2, 3, 6]);
+        assert_eq!(kb.cluster_of(5), [4, 5]);
+        assert_eq!(kb.cluster_of(0), [0, 1]);
+        assert!(kb.cluster_of(7).is_empty());
+    }
+
+    #[test]
+    fn test_keyboard_cluster_local_positions() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", "B"],
+                [{"r": 30, "rx": 3, "ry": 1}, "C", {"w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "D"],
+                [{"r": -15, "rx": 6, "ry": 0, "y": 1}, "E"]
+            ]"#,
+        )
+        .unwrap();
+
+        let clusters = kb.cluster_local_positions();
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(
+            clusters
+                .iter()
+                .map(|(_, keys)| keys.len())
+                .collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+
+        let (params, keys) = &clusters[1];
+        assert_is_close!(params.rotation, 30.0);
+        assert_eq!(keys[0].0, 2);
+        assert_is_close!(keys[0].1.x, 0.0);
+        assert_is_close!(keys[1].1.x, 1.0);
+        assert_is_close!(keys[1].1.rotation, 0.0);
+
+        // Applying the cluster transform to the local keys gives the original keys' corners
+        for (params, keys) in clusters {
+            let (sin, cos) = params.rotation.to_radians().sin_cos();
+            for (i, local) in keys {
+                assert_is_close!(local.rotation, 0.0);
+
+                let global = [kb.keys[i].corners(), kb.keys[i].corners2()];
+                let local = [local.corners(), local.corners2()];
+                for (&(x, y), &(gx, gy)) in local.iter().flatten().zip(global.iter().flatten()) {
+                    assert_is_close!(params.rx + x * cos - y * sin, gx);
+                    assert_is_close!(params.ry + x * sin + y * cos, gy);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyboard_nearest_key() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", "B", {"w": 2}, "C"],
+                [{"d": true}, "Decal"],
+                [{"r": 90, "rx": 6, "ry": 0}, "D"]
+            ]"#,
+        )
+        .unwrap();
+
+        let (index, dist) = kb.nearest_key(1.6, 0.6).unwrap();
+        assert_eq!(index, 1);
+        assert_is_close!(dist, 0.1_f64.hypot(0.1));
+
+        // The decal is nearest, but is excluded
+        let (index, dist) = kb.nearest_key(0.5, 1.5).unwrap();
+        assert_eq!(index, 0);
+        assert_is_close!(dist, 1.0);
+
+        let (index, dist) = kb.nearest_key_including_decals(0.5, 1.5).unwrap();
+        assert_eq!(index, 3);
+        assert_is_close!(dist, 0.0);
+
+        // The rotated key's centre is at (5.5, 0.5)
+        let (index, dist) = kb.nearest_key(5.5, 1.0).unwrap();
+        assert_eq!(index, 4);
+        assert_is_close!(dist, 0.5);
+
+        // Equidistant keys return the first
+        assert_eq!(kb.nearest_key(1.0, 0.5).unwrap().0, 0);
+
+        assert!(<Keyboard>::default().nearest_key(0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_keyboard_shift_rotation_origins() {
+        let mut kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A"],
+                [{"r": 30, "rx": 2, "ry": 1}, "B", {"w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "C"],
+                [{"r": -90, "rx": 5, "ry": 0, "y": 1}, "D"]
+            ]"#,
+        )
+        .unwrap();
+        let before: Vec<_> = kb
+            .keys
+            .iter()
+            .map(|key| [key.corners(), key.corners2()])
+            .collect();
+
+        kb.shift_rotation_origins(1.5, -0.5);
+
+        assert_is_close!(kb.keys[0].x, 0.0);
+        assert_is_close!(kb.keys[0].rx, 1.5);
+        assert_is_close!(kb.keys[1].rx, 3.5);
+        assert_is_close!(kb.keys[1].ry, 0.5);
+
+        for (key, before) in kb.keys.iter().zip(before) {
+            let after = [key.corners(), key.corners2()];
+            for ((x, y), (ex, ey)) in after.iter().flatten().zip(before.iter().flatten()) {
+                assert_is_close!(x, ex);
+                assert_is_close!(y, ey);
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyboard_bounding_box() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", {"w": 2}, "B"],
+                [{"x": 0.25, "w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "C"],
+                [{"d": true, "x": 5}, "Decal"]
+            ]"#,
+        )
+        .unwrap();
+        let bbox = kb.bounding_box().unwrap();
+
+        assert_is_close!(bbox.x, 0.0);
+        assert_is_close!(bbox.y, 0.0);
+        assert_is_close!(bbox.width, 3.0);
+        assert_is_close!(bbox.height, 3.0);
+
+        // The decal extends past the other keys
+        let bbox = kb.bounding_box_including_decals().unwrap();
+        assert_is_close!(bbox.x, 0.0);
+        assert_is_close!(bbox.y, 0.0);
+        assert_is_close!(bbox.width, 6.0);
+        assert_is_close!(bbox.height, 3.0);
+
+        assert!(<Keyboard>::default().bounding_box().is_none());
+        assert!(<Keyboard>::default()
+            .bounding_box_including_decals()
+            .is_none());
+    }
+
+    #[test]
+    fn test_keyboard_keys_outside() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", "B", "C"],
+                [{"w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "Enter", {"d": true, "x": 3}, "Decal"],
+                [{"r": 10, "rx": 2.5, "ry": 1}, "D"]
+            ]"#,
+        )
+        .unwrap();
+        let case = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 3.0,
+            height: 3.0,
+        };
+
+        // The Enter key's secondary rectangle pokes out to the left, and the rotated key's
+        // corners extend past the right edge. The decal is entirely outside but is ignored
+        assert_eq!(kb.keys_outside(case), [3, 5]);
+
+        let case = Rect {
+            x: -0.25,
+            width: 3.5,
+            ..case
+        };
+        assert_eq!(kb.keys_outside(case), [5]);
+
+        let case = kb.bounding_box().unwrap();
+        assert!(kb.keys_outside(case).is_empty());
+    }
+
+    #[test]
+    fn test_convex_hull() {
+        let points = vec![
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (2.0, 0.0),
+            (1.0, 0.0),
+            (0.5, 0.25),
+            (2.0, 0.0),
+        ];
+        assert_eq!(convex_hull(points), [(0.0, 0.0), (2.0, 0.0), (1.0, 1.0)]);
+
+        assert_eq!(convex_hull(vec![(1.0, 1.0), (1.0, 1.0)]), [(1.0, 1.0)]);
+        assert!(convex_hull(Vec::<(f64, f64)>::new()).is_empty());
+    }
+
+    #[test]
+    fn test_keyboard_oriented_bounding_box() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                [{"r": 30, "rx": 1, "ry": 1}, "A", "B", "C", "D"],
+                ["E", "F", "G", "H"]
+            ]"#,
+        )
+        .unwrap();
+        let (angle, rect) = kb.oriented_bounding_box().unwrap();
+
+        assert_is_close!(angle, 30.0);
+        assert_is_close!(rect.width, 4.0);
+        assert_is_close!(rect.height, 2.0);
+
+        // A key covering the rectangle has the same corners as the layout's outermost keys
+        let cover = Key {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+            rotation: angle,
+            ..Key::default()
+        };
+        let corners = [
+            kb.keys[0].corners()[0],
+            kb.keys[3].corners()[1],
+            kb.keys[7].corners()[2],
+            kb.keys[4].corners()[3],
+        ];
+        for ((x, y), (ex, ey)) in cover.corners().into_iter().zip(corners) {
+            assert_is_close!(x, ex);
+            assert_is_close!(y, ey);
+        }
+
+        // The oriented box is tighter than the axis-aligned one
+        let bbox = kb.bounding_box().unwrap();
+        assert!(rect.width * rect.height < bbox.width * bbox.height - 1.0);
+
+        // Unrotated layouts give the axis-aligned bounding box
+        let kb: Keyboard = serde_json::from_str(r#"[["A", {"w": 2}, "B"], ["C"]]"#).unwrap();
+        let (angle, rect) = kb.oriented_bounding_box().unwrap();
+        assert_is_close!(angle, 0.0);
+        assert_eq!(rect, kb.bounding_box().unwrap());
+
+        assert!(<Keyboard>::default().oriented_bounding_box().is_none());
+    }
+}
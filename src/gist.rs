@@ -0,0 +1,166 @@
+use std::{fmt, path::Path};
+
+use num_traits::real::Real;
+use serde::{
+    de::{Error as _, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::Keyboard;
+
+#[derive(Deserialize)]
+struct GistFile {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+// The gist's files in the order they appear in the response
+struct GistFiles(Vec<(String, GistFile)>);
+
+impl<'de> Deserialize<'de> for GistFiles {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct GistFilesVisitor;
+
+        impl<'de> Visitor<'de> for GistFilesVisitor {
+            type Value = GistFiles;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of files")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut files = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    files.push(entry);
+                }
+                Ok(GistFiles(files))
+            }
+        }
+
+        deserializer.deserialize_map(GistFilesVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+struct GistResponse {
+    files: GistFiles,
+}
+
+impl<T> Keyboard<T>
+where
+    T: Real + for<'de> Deserialize<'de>,
+{
+    /// Deserialises a keyboard from a [GitHub gist API][api] response, such as those used by KLE
+    /// to store layouts.
+    ///
+    /// The response is a JSON object whose `files` field maps each file name to an object
+    /// containing the file's `content` as a string. The layout is deserialised from the content
+    /// of `file` if given, otherwise from the first file with a `.json` extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the response is not a valid gist API response, if the file does not
+    /// exist, or if the file's content is not a valid KLE layout. The API truncates the content
+    /// of large files; an error is also returned in this case, since the full content must be
+    /// fetched separately from the file's `raw_url`.
+    ///
+    /// [api]: https://docs.github.com/en/rest/gists/gists#get-a-gist
+    pub fn from_gist_api_response(json: &str, file: Option<&str>) -> serde_json::Result<Self> {
+        let response: GistResponse = serde_json::from_str(json)?;
+
+        let (name, file) = (response.files.0.into_iter())
+            .find(|(name, _)| match file {
+                Some(file) => name == file,
+                None => (Path::new(name).extension())
+                    .map_or(false, |ext| ext.eq_ignore_ascii_case("json")),
+            })
+            .ok_or_else(|| {
+                serde_json::Error::custom(file.map_or_else(
+                    || "no .json file in gist".to_owned(),
+                    |file| format!("file `{file}` not found in gist"),
+                ))
+            })?;
+
+        if file.truncated {
+            return Err(serde_json::Error::custom(format!(
+                "the content of `{name}` is truncated"
+            )));
+        }
+        let content = (file.content)
+            .ok_or_else(|| serde_json::Error::custom(format!("`{name}` has no content")))?;
+
+        serde_json::from_str(&content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A trimmed down response from the gist API, with the layouts embedded as strings
+    const RESPONSE: &str = r#"{
+        "url": "https://api.github.com/gists/0123456789abcdef",
+        "id": "0123456789abcdef",
+        "description": "Keyboard Layout Editor",
+        "files": {
+            "notes.txt": {
+                "filename": "notes.txt",
+                "type": "text/plain",
+                "size": 5,
+                "truncated": false,
+                "content": "notes"
+            },
+            "layout.kbd.json": {
+                "filename": "layout.kbd.json",
+                "type": "application/json",
+                "language": "JSON",
+                "size": 42,
+                "truncated": false,
+                "content": "[{\"name\": \"first\"}, [\"A\", \"B\"]]"
+            },
+            "alt.kbd.json": {
+                "filename": "alt.kbd.json",
+                "type": "application/json",
+                "size": 30,
+                "truncated": false,
+                "content": "[{\"name\": \"alt\"}, [\"C\"]]"
+            },
+            "big.kbd.json": {
+                "filename": "big.kbd.json",
+                "size": 2000000,
+                "truncated": true,
+                "content": "[["
+            }
+        },
+        "public": true
+    }"#;
+
+    #[test]
+    fn test_keyboard_from_gist_api_response() {
+        let kb = <Keyboard>::from_gist_api_response(RESPONSE, None).unwrap();
+        assert_eq!(kb.metadata.name, "first");
+        assert_eq!(kb.keys.len(), 2);
+
+        let kb = <Keyboard>::from_gist_api_response(RESPONSE, Some("alt.kbd.json")).unwrap();
+        assert_eq!(kb.metadata.name, "alt");
+        assert_eq!(kb.keys.len(), 1);
+
+        let err = <Keyboard>::from_gist_api_response(RESPONSE, Some("missing.json")).unwrap_err();
+        assert!(err.to_string().contains("`missing.json` not found"));
+
+        let err = <Keyboard>::from_gist_api_response(RESPONSE, Some("big.kbd.json")).unwrap_err();
+        assert!(err.to_string().contains("truncated"));
+
+        assert!(<Keyboard>::from_gist_api_response(RESPONSE, Some("notes.txt")).is_err());
+        assert!(<Keyboard>::from_gist_api_response(r#"{"files": {}}"#, None).is_err());
+        assert!(<Keyboard>::from_gist_api_response("[]", None).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use num_traits::real::Real;
+
+use crate::{utils::real, Key, Keyboard, Legend};
+
+// Tolerance used when checking whether keys are adjacent
+const ADJACENCY_TOLERANCE: f64 = 1e-3;
+
+// Returns the slot and legend of the key's only legend, if it has exactly one
+fn single_legend<T>(key: &Key<T>) -> Option<(usize, &Legend)>
+where
+    T: Real,
+{
+    let mut legends = key.legends_iter();
+    legends.next().filter(|_| legends.next().is_none())
+}
+
+// Returns whether the key `next` directly follows `key` in the same row
+fn is_next_in_row<T>(key: &Key<T>, next: &Key<T>) -> bool
+where
+    T: Real,
+{
+    let tol = real(ADJACENCY_TOLERANCE);
+    key.rotation_params().approx_eq(&next.rotation_params())
+        && (key.y - next.y).abs() <= tol
+        && (key.height - next.height).abs() <= tol
+        && (key.x + key.width - next.x).abs() <= tol
+}
+
+// Returns whether `next` looks like the continuation of a word ending with `legend`
+fn continues_word(legend: &str, next: &str) -> bool {
+    let is_word = |s: &str| !s.is_empty() && s.chars().all(char::is_alphabetic);
+    is_word(legend) && is_word(next) && next.chars().next().map_or(false, char::is_lowercase)
+}
+
+impl<T> Keyboard<T>
+where
+    T: Real,
+{
+    /// Detects labels which have been split across multiple horizontally adjacent keys, returning
+    /// the indices of the keys in each group.
+    ///
+    /// This is a heuristic, and may both miss split labels and group keys which are unrelated. Keys
+    /// are grouped if they:
+    ///
+    /// * are consecutive in [`Keyboard::keys`], in the same rotation cluster, and touching
+    ///   edge to edge in the same row with the same height;
+    /// * each have exactly one legend, in the same slot; and
+    /// * have legends consisting only of letters, where every legend but the first starts with a
+    ///   lowercase letter, so the combined text looks like a single word.
+    ///
+    /// For example keys labelled `"Back"` and `"space"` are grouped, while `"Caps"` and `"Lock"`
+    /// are not. Only groups of two or more keys are returned, in layout order.
+    #[must_use]
+    pub fn detect_split_labels(&self) -> Vec<Vec<usize>> {
+        let mut groups = Vec::new();
+        let mut group: Vec<usize> = Vec::new();
+
+        for (i, key) in self.keys.iter().enumerate() {
+            let continues = group.last().map_or(false, |&prev| {
+                let prev_key = &self.keys[prev];
+                match (single_legend(prev_key), single_legend(key)) {
+                    (Some((slot, legend)), Some((next_slot, next))) => {
+                        slot == next_slot
+                            && is_next_in_row(prev_key, key)
+                            && continues_word(&legend.text, &next.text)
+                    }
+                    _ => false,
+                }
+            });
+
+            if !continues {
+                if group.len() > 1 {
+                    groups.push(std::mem::take(&mut group));
+                }
+                group.clear();
+            }
+            group.push(i);
+        }
+        if group.len() > 1 {
+            groups.push(group);
+        }
+
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_continues_word() {
+        assert!(continues_word("Back", "space"));
+        assert!(continues_word("Ent", "er"));
+        assert!(!continues_word("Caps", "Lock"));
+        assert!(!continues_word("Page", "up1"));
+        assert!(!continues_word("", "a"));
+        assert!(!continues_word("A", ""));
+    }
+
+    #[test]
+    fn test_keyboard_detect_split_labels() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["Back", "space", "Del", "Caps", "Lock"],
+                ["Pri", "nt", "Scr", {"x": 1}, "een"],
+                ["Nu", {"a": 7}, "mlock", "A\nb", "c"],
+                [{"w": 1.5}, "Ta", {"w": 0.5}, "b"]
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            kb.detect_split_labels(),
+            [vec![0, 1], vec![5, 6], vec![13, 14]]
+        );
+        assert!(<Keyboard>::default().detect_split_labels().is_empty());
+    }
+}
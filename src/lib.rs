@@ -4,6 +4,8 @@
 //! A Rust library for deserialising [Keyboard Layout Editor] files. Designed to be used in
 //! conjunction with [`serde_json`] to deserialize JSON files exported from KLE.
 //!
+//! Keyboards can also be serialised back into KLE's JSON format.
+//!
 //! # Example
 //!
 //! ![example]
@@ -36,21 +38,55 @@
 //! [`serde_json`]: https://crates.io/crates/serde_json
 //! [example]: https://raw.githubusercontent.com/staticintlucas/kle-serial-rs/main/doc/example.png
 
+mod analyzed;
+mod builder;
+mod canonical;
+mod contrast;
 mod de;
+pub mod export;
 pub mod f32;
 pub mod f64;
+#[cfg(test)]
+mod fixed;
+mod geometry;
+#[cfg(feature = "json")]
+mod gist;
+mod labels;
+mod matrix;
+mod quantize;
+#[cfg(feature = "render")]
+pub mod render;
+mod rich;
+mod scan;
+mod ser;
 mod utils;
+#[cfg(feature = "json")]
+mod validate;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+};
 
 use num_traits::real::Real;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use de::{deserialize_kle, KleKeyboard, KleLayoutIterator};
+use utils::{real, FontSize};
 
-use de::{KleKeyboard, KleLayoutIterator};
-use utils::FontSize;
+pub use analyzed::AnalyzedKeyboard;
+pub use builder::LayoutBuilder;
+pub use geometry::{Rect, RotationParams};
+pub use rich::{TextRun, TextStyle};
+pub use scan::estimate_key_count;
+#[cfg(feature = "json")]
+pub use validate::{validate_json, SchemaError};
 
 /// Colour type used for deserialising. Type alias of [`rgb::RGBA8`].
 pub type Color = rgb::RGBA8;
 
-const NUM_LEGENDS: usize = 12; // Number of legends on a key
+/// The number of legend slots on a key.
+pub const NUM_LEGENDS: usize = 12;
 
 pub(crate) mod color {
     use crate::Color;
@@ -75,9 +111,14 @@ pub(crate) mod color {
 pub struct Legend {
     /// The legend's text.
     pub text: String,
-    /// The legend size (in KLE's font size unit). KLE clamps this to the range `1..=9`.
+    /// The legend size (in KLE's font size unit). KLE clamps this to the range `1..=9`, which is
+    /// also the default when deserialising. See [`DeserializeOptions::font_size_range`].
     pub size: usize,
     /// The legend colour.
+    ///
+    /// This is always the legend's effective colour. When deserialising, KLE's `t` property and
+    /// its per-legend defaults are resolved for each legend, so there is no separate inherited
+    /// colour to look up. The default is black as in KLE; see [`Legend::is_default_color`].
     pub color: Color,
 }
 
@@ -91,6 +132,78 @@ impl Default for Legend {
     }
 }
 
+impl Legend {
+    /// Compares two legends, allowing a small difference in colour.
+    ///
+    /// The text and size must be equal, while each channel of the colour may differ by at most one.
+    /// This is useful when comparing legends whose colours have been converted between different
+    /// representations, where rounding can result in slightly different values.
+    #[must_use]
+    pub fn approx_eq(&self, other: &Self) -> bool {
+        const TOLERANCE: u8 = 1;
+
+        let (a, b) = (self.color, other.color);
+        let channels = [(a.r, b.r), (a.g, b.g), (a.b, b.b), (a.a, b.a)];
+
+        self.text == other.text
+            && self.size == other.size
+            && channels
+                .iter()
+                .all(|&(a, b)| a.max(b) - a.min(b) <= TOLERANCE)
+    }
+
+    /// Returns whether the legend's colour is KLE's default legend colour, opaque black.
+    ///
+    /// Legends with the default colour can be written without a `t` entry when starting a new
+    /// layout, although since KLE's properties carry over between keys the serialiser compares
+    /// against the previous key's colours rather than the default.
+    #[must_use]
+    pub fn is_default_color(&self) -> bool {
+        self.color == color::LEGEND
+    }
+
+    /// Returns the legend's text with leading and trailing whitespace removed.
+    ///
+    /// The deserialiser preserves legend text exactly as it appears in the layout, including any
+    /// whitespace, so this can be used when the surrounding whitespace is not wanted.
+    #[must_use]
+    pub fn trimmed(&self) -> &str {
+        self.text.trim()
+    }
+
+    /// Converts the legend's size to pixels, given the pixel size of a legend with KLE's default
+    /// size of 3.
+    ///
+    /// KLE renders legends at `6 + 2 * size` pixels at its default zoom level, so the default size
+    /// of 3 is 12 px and the maximum size of 9 is 24 px. This scales that mapping so the default
+    /// size is `base_px`, giving a result of `base_px * (6 + 2 * size) / 12`.
+    #[must_use]
+    pub fn size_px<T>(&self, base_px: T) -> T
+    where
+        T: Real,
+    {
+        // Font sizes are small integers, so there's no loss of precision
+        #[allow(clippy::cast_precision_loss)]
+        let size = self.size as f64;
+
+        base_px * real(6.0 + 2.0 * size) / real(12.0)
+    }
+}
+
+/// Compares the legend's text with a string. The legend's size and colour are ignored.
+impl PartialEq<str> for Legend {
+    fn eq(&self, other: &str) -> bool {
+        self.text == other
+    }
+}
+
+/// Compares the legend's text with a string. The legend's size and colour are ignored.
+impl PartialEq<&str> for Legend {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
 /// A struct representing a key switch.
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct Switch {
@@ -102,6 +215,21 @@ pub struct Switch {
     pub typ: String,
 }
 
+impl Switch {
+    /// Returns the mount, brand, and type values KLE would store for the switch, using KLE's
+    /// names `sm`, `sb`, and `st` respectively.
+    ///
+    /// Empty fields map to [`None`], since KLE represents them by omitting the property and the
+    /// deserialiser reads a missing property as an empty string. This is how the switch in
+    /// [`Metadata::key_switch`] is serialised. Switches on individual keys are only emitted when
+    /// they differ from the previous key's switch, since KLE's properties carry over between keys.
+    #[must_use]
+    pub fn to_kle_fields(&self) -> (Option<String>, Option<String>, Option<String>) {
+        let field = |value: &String| (!value.is_empty()).then(|| value.clone());
+        (field(&self.mount), field(&self.brand), field(&self.typ))
+    }
+}
+
 /// A struct representing a single key.
 #[derive(Debug, Clone, PartialEq)]
 #[allow(clippy::struct_excessive_bools)]
@@ -117,7 +245,7 @@ where
     /// Legends that are empty in KLE will be deserialised as [`None`].
     ///
     /// [alignment]: https://raw.githubusercontent.com/staticintlucas/kle-serial-rs/main/doc/alignment.png
-    pub legends: Vec<Option<Legend>>,
+    pub legends: [Option<Legend>; NUM_LEGENDS],
     /// The colour of the key
     pub color: Color,
     /// The X position of the key measured in keyboard units (typically 19.05 mm or 0.75 in).
@@ -224,7 +352,7 @@ where
 {
     fn default() -> Self {
         Self {
-            legends: vec![None; NUM_LEGENDS],
+            legends: Default::default(),
             color: color::KEY,
             x: T::zero(),
             y: T::zero(),
@@ -247,6 +375,160 @@ where
     }
 }
 
+impl<T> Key<T>
+where
+    T: Real,
+{
+    /// Returns an iterator over the key's populated legends along with their slot index.
+    ///
+    /// Empty legend slots are skipped. See [`Key::legends`] for the slot ordering.
+    pub fn legends_iter(&self) -> impl Iterator<Item = (usize, &Legend)> {
+        self.legends
+            .iter()
+            .enumerate()
+            .filter_map(|(i, legend)| legend.as_ref().map(|legend| (i, legend)))
+    }
+
+    /// Returns an iterator over mutable references to the key's populated legends along with their
+    /// slot index.
+    ///
+    /// Empty legend slots are skipped. See [`Key::legends`] for the slot ordering.
+    pub fn legends_iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut Legend)> {
+        self.legends
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, legend)| legend.as_mut().map(|legend| (i, legend)))
+    }
+
+    /// Returns the key with all legends removed, keeping all other properties.
+    ///
+    /// This is useful when using a key as a template for other keys.
+    #[must_use]
+    pub fn with_default_legends(mut self) -> Self {
+        self.clear_legends();
+        self
+    }
+
+    /// Returns a copy of the key moved to the position (`x`, `y`), keeping all other properties.
+    ///
+    /// The centre of rotation ([`Key::rx`] and [`Key::ry`]) is moved by the same amount, so a
+    /// rotated template key keeps the same rotation relative to its own position. This is useful
+    /// when stamping copies of a template key across a layout.
+    #[must_use]
+    pub fn clone_with_position(&self, x: T, y: T) -> Self {
+        let (dx, dy) = (x - self.x, y - self.y);
+        Self {
+            x,
+            y,
+            rx: self.rx + dx,
+            ry: self.ry + dy,
+            ..self.clone()
+        }
+    }
+
+    /// Removes all of the key's legends, setting every slot to [`None`].
+    pub fn clear_legends(&mut self) {
+        self.legends = Default::default();
+    }
+
+    /// Sets the legend at index `slot` to a new legend with the given text, size, and colour,
+    /// replacing any existing legend in that slot. If the text is empty the slot is cleared
+    /// instead, since empty legends are always stored as [`None`].
+    ///
+    /// See [`Key::legends`] for the slot ordering; for example slots 9 to 11 are the front legends.
+    /// The size is not clamped to KLE's range of `1..=9`, so sizes from forks of KLE which allow a
+    /// wider range (see [`DeserializeOptions::font_size_range`]) can also be set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot` is not less than [`NUM_LEGENDS`].
+    pub fn set_legend_at(
+        &mut self,
+        slot: usize,
+        text: impl Into<String>,
+        size: usize,
+        color: Color,
+    ) {
+        assert!(
+            slot < NUM_LEGENDS,
+            "legend slot {slot} is out of bounds (there are {NUM_LEGENDS} slots)"
+        );
+        let text = text.into();
+        self.legends[slot] = (!text.is_empty()).then_some(Legend { text, size, color });
+    }
+
+    // Compares two keys as described in Keyboard::contains_key_approx
+    fn approx_eq(&self, other: &Self, tol: T) -> bool {
+        let values = [
+            (self.x, other.x),
+            (self.y, other.y),
+            (self.width, other.width),
+            (self.height, other.height),
+            (self.x2, other.x2),
+            (self.y2, other.y2),
+            (self.width2, other.width2),
+            (self.height2, other.height2),
+            (self.rotation, other.rotation),
+            (self.rx, other.rx),
+            (self.ry, other.ry),
+        ];
+        let legends_eq = self
+            .legends
+            .iter()
+            .zip(&other.legends)
+            .all(|pair| match pair {
+                (Some(a), Some(b)) => a.approx_eq(b),
+                (a, b) => a.is_none() && b.is_none(),
+            });
+
+        values.iter().all(|&(a, b)| (a - b).abs() <= tol)
+            && legends_eq
+            && self.color == other.color
+            && self.profile == other.profile
+            && self.key_switch == other.key_switch
+            && self.ghosted == other.ghosted
+            && self.stepped == other.stepped
+            && self.homing == other.homing
+            && self.decal == other.decal
+    }
+
+    /// Returns the key's legends laid out spatially as a grid.
+    ///
+    /// The grid is indexed as `grid[row][column]`. Rows 0 to 2 are the top, centre, and bottom
+    /// rows on the top face of the key, and row 3 contains the front legends. Columns 0 to 2 are
+    /// left, centre, and right respectively. This matches the ordering of [`Key::legends`], so
+    /// `grid[row][column]` is the legend at index `row * 3 + column`.
+    #[must_use]
+    pub fn legend_grid(&self) -> [[Option<&Legend>; 3]; 4] {
+        std::array::from_fn(|row| std::array::from_fn(|col| self.legends[row * 3 + col].as_ref()))
+    }
+
+    /// Returns the key's `(width, height)` in a rotation-canonical form.
+    ///
+    /// If the key's rotation is closer to 90° or 270° than it is to 0° or 180°, the width and
+    /// height are swapped. This means, for example, a 1u &times; 2u key rotated by 90° has a
+    /// normalised size of 2u &times; 1u, the same as an unrotated 2u key.
+    ///
+    /// Only the primary rectangle (`width` and `height`) is considered. The stepped or L-shaped
+    /// part of the key (`width2` and `height2`) is ignored.
+    #[must_use]
+    pub fn normalized_size(&self) -> (T, T) {
+        let half_turn = real::<T>(180.0);
+        let rotation = self.rotation % half_turn;
+        let rotation = if rotation < T::zero() {
+            rotation + half_turn
+        } else {
+            rotation
+        };
+
+        if rotation >= real(45.0) && rotation < real(135.0) {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+}
+
 /// The background style of a KLE layout.
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct Background {
@@ -307,7 +589,64 @@ impl Default for Metadata {
     }
 }
 
+/// Options used to customise deserialisation of a KLE layout.
+///
+/// These can be passed to [`Keyboard::deserialize_with_options`] or
+/// [`KeyIterator::deserialize_with_options`]. Deserialising using [`Deserialize`] is equivalent to
+/// using [`DeserializeOptions::default()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeserializeOptions {
+    /// The profile to use for keys which do not specify a profile.
+    ///
+    /// Keys with a non-empty profile in the layout keep their own profile.
+    pub default_profile: Option<String>,
+    /// The key of the field containing the layout, for layouts embedded in another object.
+    ///
+    /// Some systems store KLE layouts wrapped in an object, for example
+    /// `{"name": "...", "layout": [...]}`. When this is set, the input is expected to be a map and
+    /// the layout is deserialised from the given field. All other fields are ignored.
+    pub wrapper_key: Option<String>,
+    /// Whether the rotation (`r`) values in the layout are in radians.
+    ///
+    /// KLE uses degrees, but some programmatically generated layouts use radians instead. When set,
+    /// rotations are converted to degrees so [`Key::rotation`] is always in degrees.
+    pub rotation_in_radians: bool,
+    /// The range legend font sizes are clamped to. The default is `1..=9`, matching KLE.
+    ///
+    /// Some forks of KLE allow a wider range of font sizes, which can be preserved by widening
+    /// this range.
+    pub font_size_range: RangeInclusive<usize>,
+    /// Whether to accept some deviations from KLE's format produced by other tools.
+    ///
+    /// When set:
+    ///
+    /// * The metadata object may be the last element of the layout rather than the first. The
+    ///   metadata is taken from the first element if it is an object, otherwise from the last
+    ///   element if that is an object. If both are objects the first is used and the last is
+    ///   ignored. An object anywhere else in the layout is still an error.
+    /// * Key positions, sizes, and rotations (`x`, `y`, `w`, `h`, `x2`, `y2`, `w2`, `h2`, `r`,
+    ///   `rx`, and `ry`) may be given as numeric strings such as `"2"` or `"1.5"`. Strings which
+    ///   are not numbers are still an error.
+    pub tolerant: bool,
+}
+
+impl Default for DeserializeOptions {
+    fn default() -> Self {
+        Self {
+            default_profile: None,
+            wrapper_key: None,
+            rotation_in_radians: false,
+            font_size_range: 1..=9,
+            tolerant: false,
+        }
+    }
+}
+
 /// A keyboard deserialised from a KLE JSON file.
+///
+/// When serialised, the layout is written using KLE's format, only emitting the properties needed
+/// to reproduce each key. The metadata object is omitted entirely if it is equal to
+/// [`Metadata::default()`].
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct Keyboard<T = f64>
 where
@@ -319,29 +658,268 @@ where
     pub keys: Vec<Key<T>>,
 }
 
-impl<'de, T> Deserialize<'de> for Keyboard<T>
+impl<T> Keyboard<T>
 where
-    T: Real + Deserialize<'de>,
+    T: Real,
 {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    /// Returns the text of every non-empty legend in the layout.
+    ///
+    /// Legends are returned in key order, and in slot order within each key. Duplicate texts are
+    /// included once for every legend they appear in; use [`Keyboard::unique_legend_texts`] for a
+    /// deduplicated set.
+    #[must_use]
+    pub fn all_legend_texts(&self) -> Vec<&str> {
+        self.keys
+            .iter()
+            .flat_map(Key::legends_iter)
+            .map(|(_, legend)| legend.text.as_str())
+            .filter(|text| !text.is_empty())
+            .collect()
+    }
+
+    /// Returns an iterator over the keys along with their indices in [`Keyboard::keys`].
+    ///
+    /// This is equivalent to `keyboard.keys.iter().enumerate()`, and is convenient when using
+    /// methods which take or return key indices, such as [`Keyboard::cluster_of`] or
+    /// [`Keyboard::adjacent_keys`].
+    pub fn iter_with_index(&self) -> impl Iterator<Item = (usize, &Key<T>)> {
+        self.keys.iter().enumerate()
+    }
+
+    /// Returns an iterator over every populated legend in the layout, along with the key it belongs
+    /// to and its slot index.
+    ///
+    /// Legends are returned in key order, and in slot order within each key. See [`Key::legends`]
+    /// for the slot ordering.
+    pub fn iter_legends(&self) -> impl Iterator<Item = (&Key<T>, usize, &Legend)> {
+        self.keys
+            .iter()
+            .flat_map(|key| key.legends_iter().map(move |(i, legend)| (key, i, legend)))
+    }
+
+    /// Returns the set of unique non-empty legend texts in the layout.
+    #[must_use]
+    pub fn unique_legend_texts(&self) -> BTreeSet<&str> {
+        self.all_legend_texts().into_iter().collect()
+    }
+
+    /// Returns the number of non-empty legends using each font size in the layout.
+    ///
+    /// Sizes are the resolved size of each legend after applying KLE's `f`, `f2`, and `fa`
+    /// properties and clamping to [`DeserializeOptions::font_size_range`]. This is useful for
+    /// spotting legends with inconsistent sizes. Legends with empty text are not counted, as in
+    /// [`Keyboard::all_legend_texts`].
+    #[must_use]
+    pub fn font_size_histogram(&self) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for (_, _, legend) in self.iter_legends() {
+            if !legend.text.is_empty() {
+                *histogram.entry(legend.size).or_insert(0) += 1;
+            }
+        }
+        histogram
+    }
+
+    /// Returns an iterator over all keys with the given colour.
+    ///
+    /// Colours are compared exactly, including the alpha channel. Since KLE colours are stored as
+    /// 8-bit values there is no loss of precision, so no tolerance is needed even when the colour
+    /// was specified using a different CSS syntax.
+    pub fn keys_by_color(&self, color: Color) -> impl Iterator<Item = &Key<T>> {
+        self.keys.iter().filter(move |key| key.color == color)
+    }
+
+    /// Returns an iterator over all ghosted keys.
+    ///
+    /// Ghosted keys are often used to mark optional or alternative key positions.
+    pub fn ghosted_keys(&self) -> impl Iterator<Item = &Key<T>> {
+        self.keys.iter().filter(|key| key.ghosted)
+    }
+
+    /// Returns an iterator over all keys which are not ghosted.
+    pub fn non_ghosted_keys(&self) -> impl Iterator<Item = &Key<T>> {
+        self.keys.iter().filter(|key| !key.ghosted)
+    }
+
+    /// Removes all ghosted keys from the layout.
+    pub fn remove_ghosted(&mut self) {
+        self.keys.retain(|key| !key.ghosted);
+    }
+
+    /// Applies `f` to every key's switch, as well as the layout's default switch in
+    /// [`Metadata::key_switch`].
+    ///
+    /// This can be used to convert a layout to a different switch platform.
+    pub fn map_switches<F>(&mut self, mut f: F)
     where
+        F: FnMut(&mut Switch),
+    {
+        f(&mut self.metadata.key_switch);
+        for key in &mut self.keys {
+            f(&mut key.key_switch);
+        }
+    }
+
+    /// Returns whether the layout contains a key equal to `key`, allowing for a difference of up to
+    /// `tol` in each of the key's positions, sizes, and rotation parameters.
+    ///
+    /// All other properties must be equal, except that legend colours are compared using
+    /// [`Legend::approx_eq`]. This is mainly useful for asserting that a transformed layout
+    /// contains a particular key despite floating point error.
+    #[must_use]
+    pub fn contains_key_approx(&self, key: &Key<T>, tol: T) -> bool {
+        self.keys.iter().any(|other| other.approx_eq(key, tol))
+    }
+}
+
+impl<T> Keyboard<T>
+where
+    T: Real,
+{
+    /// Deserialises a [`Keyboard`] using the given [`DeserializeOptions`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is not a valid KLE layout.
+    pub fn deserialize_with_options<'de, D>(
+        deserializer: D,
+        options: &DeserializeOptions,
+    ) -> Result<Self, D::Error>
+    where
+        T: Deserialize<'de>,
         D: serde::Deserializer<'de>,
     {
-        let KleKeyboard { meta, layout } = KleKeyboard::deserialize(deserializer)?;
+        let KleKeyboard { meta, layout } = deserialize_kle(deserializer, options)?;
 
         Ok(Self {
             metadata: meta.into(),
-            keys: KleLayoutIterator::new(layout).collect(),
+            keys: KleLayoutIterator::new(layout, options.clone()).collect(),
         })
     }
 }
 
+impl<'de, T> Deserialize<'de> for Keyboard<T>
+where
+    T: Real + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Self::deserialize_with_options(deserializer, &DeserializeOptions::default())
+    }
+}
+
+impl<T> Serialize for Keyboard<T>
+where
+    T: Real + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        ser::KleKeyboard::from(self).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T> Keyboard<T>
+where
+    T: Real + Serialize,
+{
+    /// Serialises the keyboard to a compact single line JSON string, matching the format of KLE's
+    /// JSON download.
+    #[must_use]
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string(self)
+            .unwrap_or_else(|_| unreachable!("serialising a layout should never fail"))
+    }
+
+    /// Serialises the keyboard to a pretty-printed JSON string, with the metadata and each row on
+    /// their own line. This is similar to the format shown in KLE's raw data tab, and is suitable
+    /// for storing layouts in version control.
+    #[must_use]
+    pub fn to_json_string_pretty(&self) -> String {
+        let ser::KleKeyboard { meta, layout } = ser::KleKeyboard::from(self);
+
+        let meta = meta.iter().map(serde_json::to_string);
+        let rows = layout.iter().map(serde_json::to_string);
+        let lines: Vec<_> = meta
+            .chain(rows)
+            .map(|line| {
+                line.unwrap_or_else(|_| unreachable!("serialising a layout should never fail"))
+            })
+            .collect();
+
+        if lines.is_empty() {
+            "[]".into()
+        } else {
+            format!("[\n  {}\n]", lines.join(",\n  "))
+        }
+    }
+
+    /// Serialises only the keyboard's keys to a JSON value, omitting the metadata object.
+    ///
+    /// The result is an array of rows in KLE's format. This is useful when storing the layout
+    /// inside another structure, with the metadata stored separately.
+    #[must_use]
+    pub fn keys_to_json(&self) -> serde_json::Value {
+        serde_json::to_value(ser::serialize_layout(&self.keys))
+            .unwrap_or_else(|_| unreachable!("serialising a layout should never fail"))
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T> std::str::FromStr for Keyboard<T>
+where
+    T: Real + for<'de> Deserialize<'de>,
+{
+    type Err = serde_json::Error;
+
+    /// Parses a keyboard from a KLE JSON string.
+    ///
+    /// Unlike [`serde_json::from_str`], a leading UTF-8 byte order mark is accepted, as added by
+    /// some editors when saving files. Leading and trailing whitespace is also accepted. JSON
+    /// comments are not supported.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s.strip_prefix('\u{feff}').unwrap_or(s))
+    }
+}
+
 /// An iterator of [`Key`]s deserialised from a KLE JSON file.
+///
+/// The metadata is discarded, but the keys yielded are identical to those in [`Keyboard::keys`]
+/// when deserialising the same input. Like in KLE, the switch set in the metadata is the layout's
+/// default and is not copied into each key's [`Key::key_switch`], which only holds per-key
+/// overrides.
 #[derive(Debug, Clone)]
 pub struct KeyIterator<T = f64>(KleLayoutIterator<T>)
 where
     T: Real;
 
+impl<T> KeyIterator<T>
+where
+    T: Real,
+{
+    /// Deserialises a [`KeyIterator`] using the given [`DeserializeOptions`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is not a valid KLE layout.
+    pub fn deserialize_with_options<'de, D>(
+        deserializer: D,
+        options: &DeserializeOptions,
+    ) -> Result<Self, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: serde::Deserializer<'de>,
+    {
+        let KleKeyboard { meta: _, layout } = deserialize_kle(deserializer, options)?;
+
+        Ok(Self(KleLayoutIterator::new(layout, options.clone())))
+    }
+}
+
 impl<'de, T> Deserialize<'de> for KeyIterator<T>
 where
     T: Real + Deserialize<'de>,
@@ -350,9 +928,7 @@ where
     where
         D: serde::Deserializer<'de>,
     {
-        let KleKeyboard { meta: _, layout } = KleKeyboard::deserialize(deserializer)?;
-
-        Ok(Self(KleLayoutIterator::new(layout)))
+        Self::deserialize_with_options(deserializer, &DeserializeOptions::default())
     }
 }
 
@@ -382,6 +958,108 @@ mod tests {
         assert_eq!(legend.color, Color::new(0, 0, 0, 255));
     }
 
+    #[test]
+    fn test_legend_approx_eq() {
+        let kb: Keyboard =
+            serde_json::from_str(r##"[[{"t": "#f00"}, "A", {"t": "#ff0000"}, "A"]]"##).unwrap();
+        let (a, b) = (
+            kb.keys[0].legends[0].as_ref().unwrap(),
+            kb.keys[1].legends[0].as_ref().unwrap(),
+        );
+        assert!(a.approx_eq(b));
+
+        let legend = Legend {
+            text: "A".into(),
+            color: Color::new(255, 1, 0, 254),
+            ..Legend::default()
+        };
+        assert!(a.approx_eq(&legend));
+
+        let legend = Legend {
+            color: Color::new(253, 0, 0, 255),
+            ..legend
+        };
+        assert!(!a.approx_eq(&legend));
+
+        let legend = Legend {
+            text: "B".into(),
+            ..a.clone()
+        };
+        assert!(!a.approx_eq(&legend));
+
+        let legend = Legend {
+            size: 4,
+            ..a.clone()
+        };
+        assert!(!a.approx_eq(&legend));
+    }
+
+    #[test]
+    fn test_legend_size_px() {
+        let legend = |size| Legend {
+            size,
+            ..Legend::default()
+        };
+
+        assert_is_close!(Legend::default().size_px(12.0), 12.0);
+        assert_is_close!(legend(3).size_px(12.0), 12.0);
+        assert_is_close!(legend(1).size_px(12.0), 8.0);
+        assert_is_close!(legend(9).size_px(12.0), 24.0);
+        assert_is_close!(legend(9).size_px(18.0_f32), 36.0);
+    }
+
+    #[test]
+    fn test_legend_eq_str() {
+        let legend = Legend {
+            text: "Enter".into(),
+            size: 5,
+            color: Color::new(255, 0, 0, 255),
+        };
+
+        assert!(legend == "Enter");
+        assert!(legend == *"Enter");
+        assert!(legend != "Esc");
+        assert!(Legend::default() == "");
+    }
+
+    #[test]
+    fn test_legend_is_default_color() {
+        assert!(Legend::default().is_default_color());
+
+        let kb: Keyboard = serde_json::from_str(
+            r##"[["A\nB", {"t": "#ff0000\n#000000"}, "C\nD", {"t": "#00000080"}, "E"]]"##,
+        )
+        .unwrap();
+        let legend = |key: usize, slot: usize| kb.keys[key].legends[slot].as_ref().unwrap();
+
+        assert!(legend(0, 0).is_default_color());
+        assert!(legend(0, 6).is_default_color());
+        assert!(!legend(1, 0).is_default_color());
+        assert!(legend(1, 6).is_default_color());
+
+        // Transparency is not the default
+        assert!(!legend(2, 0).is_default_color());
+    }
+
+    #[test]
+    fn test_legend_trimmed() {
+        let kb: Keyboard = serde_json::from_str(r#"[[" A ", "\tB\n  "]]"#).unwrap();
+        let legend = |key: usize, slot: usize| kb.keys[key].legends[slot].as_ref().unwrap();
+
+        // Whitespace is preserved, including whitespace-only legends
+        assert_eq!(legend(0, 0).text, " A ");
+        assert_eq!(legend(1, 0).text, "\tB");
+        assert_eq!(legend(1, 6).text, "  ");
+        assert_eq!(
+            serde_json::to_string(&kb).unwrap(),
+            r#"[[" A ","\tB\n  "]]"#
+        );
+
+        assert_eq!(legend(0, 0).trimmed(), "A");
+        assert_eq!(legend(1, 0).trimmed(), "B");
+        assert_eq!(legend(1, 6).trimmed(), "");
+    }
+
     #[test]
     fn test_key_default() {
         let key = <Key>::default();
@@ -411,6 +1089,212 @@ mod tests {
         assert!(!key.decal);
     }
 
+    #[test]
+    fn test_key_legends_iter() {
+        let mut key = <Key>::default();
+        key.legends[2] = Some(Legend {
+            text: "A".into(),
+            ..Legend::default()
+        });
+        key.legends[9] = Some(Legend {
+            text: "B".into(),
+            ..Legend::default()
+        });
+
+        let legends: Vec<_> = key
+            .legends_iter()
+            .map(|(i, l)| (i, l.text.as_str()))
+            .collect();
+        assert_eq!(legends, [(2, "A"), (9, "B")]);
+
+        for (_, legend) in key.legends_iter_mut() {
+            legend.size = 5;
+        }
+        assert_eq!(key.legends[2].as_ref().unwrap().size, 5);
+        assert_eq!(key.legends[9].as_ref().unwrap().size, 5);
+        assert!(key.legends[0].is_none());
+    }
+
+    #[test]
+    fn test_key_with_default_legends() {
+        let kb: Keyboard = serde_json::from_str(
+            r##"[[{"c": "#ff0000", "p": "DSA", "w": 2}, "A\nB\nC\nD\nE\nF\nG\nH\nI\nJ\nK\nL"]]"##,
+        )
+        .unwrap();
+        let key = kb.keys[0].clone();
+        assert!(key.legends.iter().all(Option::is_some));
+
+        let template = key.clone().with_default_legends();
+        assert!(template.legends.iter().all(Option::is_none));
+        assert_eq!(template.color, key.color);
+        assert_eq!(template.profile, "DSA");
+        assert_is_close!(template.width, 2.0);
+
+        let mut key = key;
+        key.clear_legends();
+        assert_eq!(key, template);
+    }
+
+    #[test]
+    fn test_key_clone_with_position() {
+        let template = Key {
+            width: 1.25,
+            width2: 1.25,
+            color: Color::new(255, 0, 0, 255),
+            ..Key::default()
+        };
+
+        let keys: Vec<_> = (0..3)
+            .flat_map(|row| (0..3).map(move |col| (row, col)))
+            .map(|(row, col)| template.clone_with_position(f64::from(col) * 1.25, f64::from(row)))
+            .collect();
+        assert_eq!(keys.len(), 9);
+        let positions = (0..3).flat_map(|row| (0..3).map(move |col| (col, row)));
+        for (key, (col, row)) in keys.iter().zip(positions) {
+            assert_is_close!(key.x, f64::from(col) * 1.25);
+            assert_is_close!(key.y, f64::from(row));
+            assert_eq!(key.color, template.color);
+            assert_is_close!(key.width, 1.25);
+        }
+
+        // The centre of rotation moves with the key
+        let template = Key {
+            rotation: 10.0,
+            rx: 0.5,
+            ry: 0.5,
+            ..Key::default()
+        };
+        let key = template.clone_with_position(2.0, 1.0);
+        assert_is_close!(key.rx, 2.5);
+        assert_is_close!(key.ry, 1.5);
+        let ((x, y), (tx, ty)) = (key.center(), template.center());
+        assert_is_close!(x - tx, 2.0);
+        assert_is_close!(y - ty, 1.0);
+    }
+
+    #[test]
+    fn test_key_set_legend_at() {
+        let mut key = <Key>::default();
+        key.set_legend_at(10, "Fn", 2, Color::new(255, 0, 0, 255));
+        key.set_legend_at(0, String::from("A"), 12, color::LEGEND);
+        key.set_legend_at(4, "B", 0, color::LEGEND);
+
+        let front = key.legend_grid()[3][1].unwrap();
+        assert_eq!(front.text, "Fn");
+        assert_eq!(front.size, 2);
+        assert_eq!(front.color, Color::new(255, 0, 0, 255));
+        assert_eq!(key.legends[0].as_ref().unwrap().size, 12);
+        assert_eq!(key.legends[4].as_ref().unwrap().size, 0);
+        assert_eq!(key.legends_iter().count(), 3);
+
+        // Setting an empty legend clears the slot
+        key.set_legend_at(10, "", 3, color::LEGEND);
+        assert!(key.legends[10].is_none());
+        assert_eq!(key.legends_iter().count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "legend slot 12 is out of bounds")]
+    fn test_key_set_legend_at_out_of_bounds() {
+        <Key>::default().set_legend_at(NUM_LEGENDS, "A", 3, color::LEGEND);
+    }
+
+    #[test]
+    fn test_key_legend_grid() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[[{"a": 0}, "TL\nBL\nTR\nBR\nFL\nFR\nCL\nCR\nTC\nCC\nBC\nFC"]]"#,
+        )
+        .unwrap();
+        let grid = kb.keys[0].legend_grid();
+        let text = grid.map(|row| row.map(|l| l.unwrap().text.as_str()));
+
+        assert_eq!(
+            text,
+            [
+                ["TL", "TC", "TR"],
+                ["CL", "CC", "CR"],
+                ["BL", "BC", "BR"],
+                ["FL", "FC", "FR"],
+            ]
+        );
+
+        let key = <Key>::default();
+        assert!(key.legend_grid().iter().flatten().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_key_normalized_size() {
+        let key = Key {
+            width: 2.0,
+            ..Key::default()
+        };
+        let (w, h) = key.normalized_size();
+        assert_is_close!(w, 2.0);
+        assert_is_close!(h, 1.0);
+
+        let rotated = Key {
+            height: 2.0,
+            rotation: 90.0,
+            ..Key::default()
+        };
+        let (w, h) = rotated.normalized_size();
+        assert_is_close!(w, 2.0);
+        assert_is_close!(h, 1.0);
+
+        let rotated = Key {
+            width: 2.0,
+            rotation: -90.0,
+            ..Key::default()
+        };
+        let (w, h) = rotated.normalized_size();
+        assert_is_close!(w, 1.0);
+        assert_is_close!(h, 2.0);
+
+        let rotated = Key {
+            width: 2.0,
+            rotation: 180.0 + 15.0,
+            ..Key::default()
+        };
+        let (w, h) = rotated.normalized_size();
+        assert_is_close!(w, 2.0);
+        assert_is_close!(h, 1.0);
+    }
+
+    #[test]
+    fn test_switch_to_kle_fields() {
+        assert_eq!(Switch::default().to_kle_fields(), (None, None, None));
+
+        let switch = Switch {
+            mount: "cherry".into(),
+            brand: String::new(),
+            typ: "MX1A-11Nx".into(),
+        };
+        let (sm, sb, st) = switch.to_kle_fields();
+        assert_eq!(sm.as_deref(), Some("cherry"));
+        assert_eq!(sb, None);
+        assert_eq!(st.as_deref(), Some("MX1A-11Nx"));
+
+        let switch = Switch {
+            brand: "cherry".into(),
+            ..switch
+        };
+        let (sm, sb, st) = switch.to_kle_fields();
+        let json = format!(
+            r#"[{{"switchMount": "{}", "switchBrand": "{}", "switchType": "{}"}}]"#,
+            sm.unwrap(),
+            sb.unwrap(),
+            st.unwrap()
+        );
+        let kb: Keyboard = serde_json::from_str(&json).unwrap();
+        assert_eq!(kb.metadata.key_switch, switch);
+
+        let json = serde_json::to_string(&kb).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"switchMount":"cherry","switchBrand":"cherry","switchType":"MX1A-11Nx"}]"#
+        );
+    }
+
     #[test]
     fn test_metadata_default() {
         let meta = Metadata::default();
@@ -466,6 +1350,540 @@ mod tests {
         assert!(serde_json::from_str::<Keyboard>("null").is_err());
     }
 
+    #[test]
+    fn test_keyboard_legend_texts() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["Esc", "!\n1", "@\n2"],
+                ["Tab", "Q", {"a": 7}, "Esc"]
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            kb.all_legend_texts(),
+            ["Esc", "!", "1", "@", "2", "Tab", "Q", "Esc"]
+        );
+        assert_eq!(
+            kb.unique_legend_texts().into_iter().collect::<Vec<_>>(),
+            ["!", "1", "2", "@", "Esc", "Q", "Tab"]
+        );
+    }
+
+    #[test]
+    fn test_keyboard_font_size_histogram() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["Esc", {"f": 4}, "!\n1", "@\n2"],
+                [{"f": 3, "f2": 5}, "Tab\nTab", {"fa": [9, 2]}, "Q\nq\n\n\n\n\nW", ""]
+            ]"#,
+        )
+        .unwrap();
+
+        let histogram = kb.font_size_histogram();
+        assert_eq!(
+            histogram.into_iter().collect::<Vec<_>>(),
+            [(2, 1), (3, 3), (4, 4), (5, 1), (9, 1)]
+        );
+
+        assert!(<Keyboard>::default().font_size_histogram().is_empty());
+    }
+
+    #[test]
+    fn test_keyboard_contains_key_approx() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", {"w": 1.5}, "B"],
+                [{"r": 15, "rx": 1, "ry": 1}, "C"]
+            ]"#,
+        )
+        .unwrap();
+
+        let mut key = kb.keys[1].clone();
+        key.x += 1e-9;
+        key.width -= 1e-9;
+        assert!(kb.contains_key_approx(&key, 1e-6));
+        assert!(!kb.contains_key_approx(&key, 0.0));
+
+        let mut key = kb.keys[2].clone();
+        key.rotation = 15.0 + 1e-3;
+        assert!(!kb.contains_key_approx(&key, 1e-6));
+        assert!(kb.contains_key_approx(&key, 1e-2));
+
+        let mut key = kb.keys[0].clone();
+        key.legends[0].as_mut().unwrap().color.r += 1;
+        assert!(kb.contains_key_approx(&key, 0.0));
+        key.legends[0].as_mut().unwrap().text = "Z".into();
+        assert!(!kb.contains_key_approx(&key, 1.0));
+
+        assert!(!<Keyboard>::default().contains_key_approx(&kb.keys[0], 1.0));
+    }
+
+    #[test]
+    fn test_keyboard_iter_with_index() {
+        let kb: Keyboard = serde_json::from_str(r#"[["A", "B"], ["C"]]"#).unwrap();
+
+        let keys: Vec<_> = kb
+            .iter_with_index()
+            .map(|(i, key)| (i, key.legends[0].as_ref().unwrap().text.as_str()))
+            .collect();
+        assert_eq!(keys, [(0, "A"), (1, "B"), (2, "C")]);
+    }
+
+    #[test]
+    fn test_keyboard_iter_legends() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A\nB", "C"],
+                ["", "\n\nD\n\n\n\n\n\n\nE"]
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(kb.iter_legends().count(), 5);
+
+        let legends: Vec<_> = kb
+            .iter_legends()
+            .map(|(key, i, legend)| (key.x, i, legend.text.as_str()))
+            .collect();
+        assert_eq!(
+            legends,
+            [
+                (0.0, 0, "A"),
+                (0.0, 6, "B"),
+                (1.0, 0, "C"),
+                (1.0, 2, "D"),
+                (1.0, 4, "E")
+            ]
+        );
+
+        assert_eq!(<Keyboard>::default().iter_legends().count(), 0);
+    }
+
+    #[test]
+    fn test_keyboard_keys_by_color() {
+        let kb: Keyboard = serde_json::from_str(
+            r##"[["A", {"c": "#ff0000"}, "B", {"c": "rgb(204, 204, 204)"}, "C"]]"##,
+        )
+        .unwrap();
+
+        let keys: Vec<_> = kb
+            .keys_by_color(Color::new(0xCC, 0xCC, 0xCC, 0xFF))
+            .map(|k| k.legends[0].as_ref().unwrap().text.as_str())
+            .collect();
+        assert_eq!(keys, ["A", "C"]);
+
+        assert_eq!(kb.keys_by_color(Color::new(0, 0, 0, 0)).count(), 0);
+    }
+
+    #[test]
+    fn test_keyboard_ghosted_keys() {
+        let mut kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", {"g": true}, "B", "C"],
+                [{"g": false}, "D", {"g": true}, "E"]
+            ]"#,
+        )
+        .unwrap();
+        let texts = |keys: Vec<&Key>| -> Vec<_> {
+            keys.iter()
+                .map(|k| k.legends[0].as_ref().unwrap().text.clone())
+                .collect()
+        };
+
+        assert_eq!(texts(kb.ghosted_keys().collect()), ["B", "C", "E"]);
+        assert_eq!(texts(kb.non_ghosted_keys().collect()), ["A", "D"]);
+
+        kb.remove_ghosted();
+        assert_eq!(texts(kb.keys.iter().collect()), ["A", "D"]);
+        assert_eq!(kb.ghosted_keys().count(), 0);
+    }
+
+    #[test]
+    fn test_keyboard_map_switches() {
+        let mut kb: Keyboard = serde_json::from_str(
+            r#"[
+                {"switchMount": "cherry", "switchBrand": "cherry", "switchType": "MX1A-11Nx"},
+                [{"sm": "cherry", "sb": "cherry"}, "A", {"sb": "gateron"}, "B"],
+                [{"sb": "cherry"}, "C"]
+            ]"#,
+        )
+        .unwrap();
+
+        kb.map_switches(|switch| {
+            if switch.brand == "cherry" {
+                "kailh".clone_into(&mut switch.brand);
+            }
+        });
+
+        assert_eq!(kb.metadata.key_switch.brand, "kailh");
+        assert_eq!(kb.metadata.key_switch.typ, "MX1A-11Nx");
+        let brands: Vec<_> = kb
+            .keys
+            .iter()
+            .map(|k| k.key_switch.brand.as_str())
+            .collect();
+        assert_eq!(brands, ["kailh", "gateron", "kailh"]);
+        assert!(kb.keys.iter().all(|k| k.key_switch.mount == "cherry"));
+    }
+
+    #[test]
+    fn test_keyboard_deserialize_with_options() {
+        let options = DeserializeOptions {
+            default_profile: Some("SA".into()),
+            ..DeserializeOptions::default()
+        };
+        let kb = Keyboard::<f64>::deserialize_with_options(
+            &mut serde_json::Deserializer::from_str(r#"[["A", "B"], [{"p": "DSA"}, "C"]]"#),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(kb.keys.len(), 3);
+        assert_eq!(kb.keys[0].profile, "SA");
+        assert_eq!(kb.keys[1].profile, "SA");
+        assert_eq!(kb.keys[2].profile, "DSA");
+
+        let keys: Vec<_> = KeyIterator::<f64>::deserialize_with_options(
+            &mut serde_json::Deserializer::from_str(r#"[["A"]]"#),
+            &options,
+        )
+        .unwrap()
+        .collect();
+        assert_eq!(keys[0].profile, "SA");
+    }
+
+    #[test]
+    fn test_keyboard_deserialize_rotation_in_radians() {
+        let options = DeserializeOptions {
+            rotation_in_radians: true,
+            ..DeserializeOptions::default()
+        };
+        let kb = Keyboard::<f64>::deserialize_with_options(
+            &mut serde_json::Deserializer::from_str(
+                r#"[[{"r": 0.5235987755982988, "rx": 1}, "A", "B"], [{"r": -1.5707963267948966}, "C"]]"#,
+            ),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(kb.keys.len(), 3);
+        assert_is_close!(kb.keys[0].rotation, 30.0);
+        assert_is_close!(kb.keys[1].rotation, 30.0);
+        assert_is_close!(kb.keys[2].rotation, -90.0);
+        assert_is_close!(kb.keys[2].rx, 1.0);
+    }
+
+    #[test]
+    fn test_keyboard_deserialize_font_size_range() {
+        let json = r#"[[{"f": 12}, "A", {"fa": [0, 2, 12]}, "B\nC\nD"]]"#;
+
+        let kb: Keyboard = serde_json::from_str(json).unwrap();
+        let sizes: Vec<_> = kb.iter_legends().map(|(_, _, l)| l.size).collect();
+        assert_eq!(sizes, [9, 9, 9, 2]);
+
+        let options = DeserializeOptions {
+            font_size_range: 1..=16,
+            ..DeserializeOptions::default()
+        };
+        let kb = Keyboard::<f64>::deserialize_with_options(
+            &mut serde_json::Deserializer::from_str(json),
+            &options,
+        )
+        .unwrap();
+        let sizes: Vec<_> = kb.iter_legends().map(|(_, _, l)| l.size).collect();
+        assert_eq!(sizes, [12, 12, 12, 2]);
+    }
+
+    #[test]
+    fn test_keyboard_serialize() {
+        let kb: Keyboard = serde_json::from_str(r#"[["A", "B"], ["C"]]"#).unwrap();
+        assert_eq!(serde_json::to_string(&kb).unwrap(), r#"[["A","B"],["C"]]"#);
+
+        let kb: Keyboard = serde_json::from_str(r#"[{"name": "test"}, ["A"]]"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&kb).unwrap(),
+            r#"[{"name":"test"},["A"]]"#
+        );
+
+        assert_eq!(serde_json::to_string(&<Keyboard>::default()).unwrap(), "[]");
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_keyboard_to_json_string() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                {"name": "test"},
+                ["A", {"w": 2}, "B"],
+                ["C"]
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            kb.to_json_string(),
+            r#"[{"name":"test"},["A",{"w":2},"B"],["C"]]"#
+        );
+        assert_eq!(
+            kb.to_json_string_pretty(),
+            r#"[
+  {"name":"test"},
+  ["A",{"w":2},"B"],
+  ["C"]
+]"#
+        );
+
+        let parsed: Keyboard = serde_json::from_str(&kb.to_json_string_pretty()).unwrap();
+        assert_eq!(parsed, kb);
+
+        assert_eq!(<Keyboard>::default().to_json_string(), "[]");
+        assert_eq!(<Keyboard>::default().to_json_string_pretty(), "[]");
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_keyboard_from_str() {
+        let json = r#"[{"name": "test"}, ["A", "B"]]"#;
+        let expected: Keyboard = serde_json::from_str(json).unwrap();
+
+        // serde_json rejects a leading BOM
+        let with_bom = format!("\u{feff}{json}");
+        assert!(serde_json::from_str::<Keyboard>(&with_bom).is_err());
+
+        for input in [
+            with_bom,
+            format!("\u{feff} \r\n\t{json}\n \n"),
+            format!("  {json}  "),
+        ] {
+            assert_eq!(input.parse::<Keyboard>().unwrap(), expected);
+        }
+
+        // Only a single leading BOM is stripped, and comments are still rejected
+        assert!("\u{feff}\u{feff}[]".parse::<Keyboard>().is_err());
+        assert!("[]\u{feff}".parse::<Keyboard>().is_err());
+        assert!("// comment\n[]".parse::<Keyboard>().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_keyboard_keys_to_json() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                {"name": "test"},
+                ["A", {"w": 2}, "B"],
+                ["C"]
+            ]"#,
+        )
+        .unwrap();
+
+        let json = kb.keys_to_json();
+        let rows = json.as_array().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(serde_json::Value::is_array));
+        assert_eq!(json.to_string(), r#"[["A",{"w":2},"B"],["C"]]"#);
+
+        let parsed: Keyboard = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.keys, kb.keys);
+
+        assert_eq!(<Keyboard>::default().keys_to_json(), serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_keyboard_serialize_round_trip() {
+        let kb: Keyboard = serde_json::from_str(
+            r##"[
+                {"name": "test", "author": "me", "backcolor": "#222222", "pcb": true},
+                [{"c": "#ff0000", "t": "#00ff00\n\n#0000ff", "a": 7}, "A\nB\nC", "D"],
+                [{"x": 0.25, "w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "Enter"],
+                [{"y": -0.5, "f": 5, "f2": 2, "p": "DSA", "sm": "cherry"}, "E\nF", {"g": true}, "G"],
+                [{"fa": [1, 0, 4, 6], "n": true}, "H\nI\nJ\nK", {"d": true, "l": true}, "L"],
+                [{"r": 15, "rx": 1, "ry": 2}, "M", "N"],
+                [{"y": 0.5, "x": -0.5}, "O"],
+                [{"r": -15, "rx": 3}, "P"]
+            ]"##,
+        )
+        .unwrap();
+
+        let json = serde_json::to_string(&kb).unwrap();
+        let result: Keyboard = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(result, kb);
+    }
+
+    #[test]
+    fn test_keyboard_metadata_text_round_trip() {
+        let name = "Tom's \"60%\" <board> & \\ ⌨️\ttabbed";
+        let author = "**me** — [site](https://example.com)";
+        let notes = "# Notes\r\n\n* first line\n* second line with `code`\n\n\u{0}end\n";
+
+        let json = serde_json::to_string(&serde_json::json!([
+            {"name": name, "author": author, "notes": notes},
+            ["A"]
+        ]))
+        .unwrap();
+        let kb: Keyboard = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(kb.metadata.name, name);
+        assert_eq!(kb.metadata.author, author);
+        assert_eq!(kb.metadata.notes, notes);
+
+        let result: Keyboard = serde_json::from_str(&serde_json::to_string(&kb).unwrap()).unwrap();
+        assert_eq!(result.metadata.name.as_bytes(), name.as_bytes());
+        assert_eq!(result.metadata.author.as_bytes(), author.as_bytes());
+        assert_eq!(result.metadata.notes.as_bytes(), notes.as_bytes());
+    }
+
+    #[test]
+    fn test_keyboard_deserialize_wrapped() {
+        let json = r#"{
+            "id": 42,
+            "data": {"layout": "not this one"},
+            "name": "wrapper",
+            "layout": [{"name": "inner"}, ["A", "B"], ["C"]]
+        }"#;
+        let options = DeserializeOptions {
+            wrapper_key: Some("layout".into()),
+            ..DeserializeOptions::default()
+        };
+
+        let kb = Keyboard::<f64>::deserialize_with_options(
+            &mut serde_json::Deserializer::from_str(json),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(kb.metadata.name, "inner");
+        assert_eq!(kb.keys.len(), 3);
+
+        let keys = KeyIterator::<f64>::deserialize_with_options(
+            &mut serde_json::Deserializer::from_str(json),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(keys.count(), 3);
+
+        let options = DeserializeOptions {
+            wrapper_key: Some("keys".into()),
+            ..DeserializeOptions::default()
+        };
+        assert!(Keyboard::<f64>::deserialize_with_options(
+            &mut serde_json::Deserializer::from_str(json),
+            &options
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_keyboard_deserialize_trailing_metadata() {
+        fn de(json: &str, options: &DeserializeOptions) -> serde_json::Result<Keyboard> {
+            Keyboard::deserialize_with_options(
+                &mut serde_json::Deserializer::from_str(json),
+                options,
+            )
+        }
+
+        let json = r#"[["A", "B"], ["C"], {"name": "trailing"}]"#;
+        let options = DeserializeOptions {
+            tolerant: true,
+            ..DeserializeOptions::default()
+        };
+
+        assert!(serde_json::from_str::<Keyboard>(json).is_err());
+
+        let kb = de(json, &options).unwrap();
+        assert_eq!(kb.metadata.name, "trailing");
+        assert_eq!(kb.keys.len(), 3);
+
+        // The leading metadata takes precedence
+        let kb = de(
+            r#"[{"name": "leading"}, ["A"], {"name": "trailing"}]"#,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(kb.metadata.name, "leading");
+        assert_eq!(kb.keys.len(), 1);
+
+        // Without any metadata the layout is unaffected
+        let kb = de(r#"[["A"], ["B"]]"#, &options).unwrap();
+        assert_eq!(kb.metadata, Metadata::default());
+        assert_eq!(kb.keys.len(), 2);
+
+        // Metadata anywhere else is still an error
+        assert!(de(r#"[["A"], {"name": "middle"}, ["B"]]"#, &options).is_err());
+
+        let options = DeserializeOptions {
+            wrapper_key: Some("layout".into()),
+            ..options
+        };
+        let kb = de(r#"{"layout": [["A"], {"name": "wrapped"}]}"#, &options).unwrap();
+        assert_eq!(kb.metadata.name, "wrapped");
+    }
+
+    #[test]
+    fn test_keyboard_deserialize_numeric_strings() {
+        fn de(json: &str, options: &DeserializeOptions) -> serde_json::Result<Keyboard> {
+            Keyboard::deserialize_with_options(
+                &mut serde_json::Deserializer::from_str(json),
+                options,
+            )
+        }
+
+        let json = r#"[
+            [{"w": "2", "h": " 1.5 "}, "A", {"x": "0.25"}, "B"],
+            [{"r": "15", "rx": "1", "ry": 2, "y": "-0.5"}, "C"]
+        ]"#;
+        let options = DeserializeOptions {
+            tolerant: true,
+            ..DeserializeOptions::default()
+        };
+
+        assert!(serde_json::from_str::<Keyboard>(json).is_err());
+
+        let kb = de(json, &options).unwrap();
+        assert_eq!(kb.keys.len(), 3);
+        assert_is_close!(kb.keys[0].width, 2.0);
+        assert_is_close!(kb.keys[0].width2, 2.0);
+        assert_is_close!(kb.keys[0].height, 1.5);
+        assert_is_close!(kb.keys[1].x, 2.25);
+        assert_is_close!(kb.keys[2].rotation, 15.0);
+        assert_is_close!(kb.keys[2].rx, 1.0);
+        assert_is_close!(kb.keys[2].y, 1.5);
+
+        // The result is the same as the equivalent layout using numbers
+        let expected: Keyboard = serde_json::from_str(
+            r#"[
+                [{"w": 2, "h": 1.5}, "A", {"x": 0.25}, "B"],
+                [{"r": 15, "rx": 1, "ry": 2, "y": -0.5}, "C"]
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(kb, expected);
+
+        assert!(de(r#"[[{"w": "wide"}, "A"]]"#, &options).is_err());
+        assert!(de(r#"[[{"w": ""}, "A"]]"#, &options).is_err());
+        assert!(de(r#"[[{"w": true}, "A"]]"#, &options).is_err());
+        assert!(de(r#"[[{"w": "NaN"}, "A"]]"#, &options).is_err());
+        assert!(de(r#"[[{"w": "inf"}, "A"]]"#, &options).is_err());
+        assert!(de(r#"[[{"w": "-infinity"}, "A"]]"#, &options).is_err());
+    }
+
+    #[test]
+    fn test_key_iterator_matches_keyboard() {
+        let json = r##"[
+            {"switchMount": "cherry", "switchBrand": "gateron", "switchType": "yellow"},
+            [{"c": "#ff0000", "p": "SA"}, "A", {"sb": "kailh"}, "B"],
+            ["C"]
+        ]"##;
+
+        let kb: Keyboard = serde_json::from_str(json).unwrap();
+        let keys: Vec<_> = serde_json::from_str::<KeyIterator>(json).unwrap().collect();
+
+        assert_eq!(keys, kb.keys);
+        assert_eq!(kb.metadata.key_switch.brand, "gateron");
+        assert_eq!(keys[0].key_switch.brand, "");
+        assert_eq!(keys[1].key_switch.brand, "kailh");
+    }
+
     #[test]
     fn test_key_iterator_deserialize() {
         let keys: Vec<_> = serde_json::from_str::<KeyIterator>(
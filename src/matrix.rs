@@ -0,0 +1,133 @@
+use std::cmp::Ordering;
+
+use num_traits::real::Real;
+
+use crate::{Keyboard, Legend, NUM_LEGENDS};
+
+impl<T> Keyboard<T>
+where
+    T: Real,
+{
+    /// Assigns a switch matrix position to every key in reading order, writing it into the legend
+    /// at index `slot` as `row,col`.
+    ///
+    /// Keys are grouped into physical rows by the Y coordinate of their
+    /// [`centre`](crate::Key::center), so rotation is taken into account. Starting from the topmost
+    /// key, every key whose centre is at most `tol` keyboard units below the first key in the row
+    /// is placed in the same row, and the next key starts a new row. A `tol` of around half a unit
+    /// groups keys in a conventional staggered layout, while allowing for slightly offset or
+    /// rotated keys. Within each row, columns are numbered from left to right by each key's
+    /// centre. Rows and columns both start from zero.
+    ///
+    /// If the slot already contains a legend only its text is replaced, otherwise a new legend
+    /// with the default size and colour is added. Decals are not physical keys, so are not assigned
+    /// a position and are left unchanged. The result can be exported using
+    /// [`export::to_matrix_csv`](crate::export::to_matrix_csv).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot` is not less than [`NUM_LEGENDS`].
+    pub fn auto_assign_matrix(&mut self, slot: usize, tol: T) {
+        assert!(
+            slot < NUM_LEGENDS,
+            "legend slot {slot} is out of bounds (there are {NUM_LEGENDS} slots)"
+        );
+
+        let mut centers: Vec<_> = (self.keys.iter().enumerate())
+            .filter(|(_, key)| !key.decal)
+            .map(|(i, key)| (i, key.center()))
+            .collect();
+        centers.sort_by(|(_, (_, a)), (_, (_, b))| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        let mut rows: Vec<Vec<(usize, T)>> = Vec::new();
+        let mut row_y = None;
+        for (i, (x, y)) in centers {
+            match row_y {
+                Some(row_y) if y - row_y <= tol => {}
+                _ => {
+                    rows.push(Vec::new());
+                    row_y = Some(y);
+                }
+            }
+            if let Some(row) = rows.last_mut() {
+                row.push((i, x));
+            }
+        }
+
+        for (row, keys) in rows.iter_mut().enumerate() {
+            keys.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            for (col, &(i, _)) in keys.iter().enumerate() {
+                let text = format!("{row},{col}");
+                match self.keys[i].legends[slot] {
+                    Some(ref mut legend) => legend.text = text,
+                    ref mut legend @ None => {
+                        *legend = Some(Legend {
+                            text,
+                            ..Legend::default()
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{export::to_matrix_csv, Color};
+
+    #[test]
+    fn test_keyboard_auto_assign_matrix() {
+        let mut kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["Esc", "Q", "W"],
+                [{"x": 0.25, "y": 0.1}, "A", {"y": -0.2}, "S", {"y": 0.1}, "D"],
+                [{"d": true}, "Decal", {"d": false}, "Z"],
+                [{"r": 5, "rx": 2, "ry": 2}, "X"]
+            ]"#,
+        )
+        .unwrap();
+        kb.keys[7].set_legend_at(9, "Front", 3, Color::new(255, 0, 0, 255));
+        kb.auto_assign_matrix(9, 0.5);
+
+        let positions: Vec<_> = (kb.keys.iter())
+            .map(|key| key.legends[9].as_ref().map(|legend| legend.text.as_str()))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                Some("0,0"),
+                Some("0,1"),
+                Some("0,2"),
+                Some("1,0"),
+                Some("1,1"),
+                Some("1,2"),
+                None,
+                Some("2,0"),
+                Some("2,1"),
+            ]
+        );
+
+        // Existing legends keep their colour, and other legends are unchanged
+        let legend = kb.keys[7].legends[9].as_ref().unwrap();
+        assert_eq!(legend.color, Color::new(255, 0, 0, 255));
+        assert_eq!(kb.keys[7].legends[0].as_ref().unwrap().text, "Z");
+
+        assert!(to_matrix_csv(&kb, 9).is_ok());
+
+        // With a small tolerance the staggered keys are put in separate rows
+        kb.auto_assign_matrix(11, 0.05);
+        let text = |i: usize| kb.keys[i].legends[11].as_ref().unwrap().text.clone();
+        assert_eq!(text(4), "1,0");
+        assert_eq!(text(5), "2,0");
+        assert_eq!(text(3), "3,0");
+        assert_eq!(text(7), "4,0");
+    }
+
+    #[test]
+    #[should_panic(expected = "legend slot 12 is out of bounds")]
+    fn test_keyboard_auto_assign_matrix_out_of_bounds() {
+        <Keyboard>::default().auto_assign_matrix(NUM_LEGENDS, 0.5);
+    }
+}
@@ -0,0 +1,103 @@
+use num_traits::real::Real;
+
+use crate::{Color, Keyboard};
+
+// Returns the squared Euclidean distance between two colours' RGB values
+fn distance_sq(a: Color, b: Color) -> u32 {
+    let channel = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+    channel(a.r, b.r) + channel(a.g, b.g) + channel(a.b, b.b)
+}
+
+// Returns the colour in the palette nearest to the given colour, or None if the palette is empty
+fn nearest(color: Color, palette: &[Color]) -> Option<Color> {
+    // min_by_key returns the first of equally near colours
+    palette
+        .iter()
+        .copied()
+        .min_by_key(|&c| distance_sq(color, c))
+}
+
+impl<T> Keyboard<T>
+where
+    T: Real,
+{
+    /// Replaces the colour of every key and legend with the nearest colour in `palette`.
+    ///
+    /// Distance is measured as the Euclidean distance between the colours' red, green, and blue
+    /// values, ignoring alpha. Each colour is replaced by the palette colour entirely, including
+    /// its alpha. If multiple palette colours are equally near, the first is used. The layout is
+    /// unchanged if `palette` is empty.
+    pub fn quantize_colors(&mut self, palette: &[Color]) {
+        let quantize = |color: &mut Color| {
+            if let Some(nearest) = nearest(*color, palette) {
+                *color = nearest;
+            }
+        };
+
+        for key in &mut self.keys {
+            quantize(&mut key.color);
+            for (_, legend) in key.legends_iter_mut() {
+                quantize(&mut legend.color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest() {
+        let (black, white) = (Color::new(0, 0, 0, 255), Color::new(255, 255, 255, 255));
+
+        assert_eq!(
+            nearest(Color::new(10, 20, 30, 0), &[black, white]),
+            Some(black)
+        );
+        assert_eq!(
+            nearest(Color::new(200, 100, 200, 255), &[black, white]),
+            Some(white)
+        );
+        assert_eq!(
+            nearest(Color::new(1, 1, 1, 255), &[black, black, white]),
+            Some(black)
+        );
+        assert_eq!(nearest(black, &[]), None);
+
+        // Equally near colours prefer the first
+        let grey = Color::new(128, 128, 128, 255);
+        let (lo, hi) = (
+            Color::new(127, 128, 128, 255),
+            Color::new(129, 128, 128, 255),
+        );
+        assert_eq!(nearest(grey, &[lo, hi]), Some(lo));
+        assert_eq!(nearest(grey, &[hi, lo]), Some(hi));
+    }
+
+    #[test]
+    fn test_keyboard_quantize_colors() {
+        let mut kb: Keyboard = serde_json::from_str(
+            r##"[
+                [{"c": "#102030", "t": "#eeeeee"}, "A", {"c": "#e0d0f0", "t": "#333333\n#c0c0c0"}, "B\nC"],
+                [{"c": "#ff0000"}, "D"]
+            ]"##,
+        )
+        .unwrap();
+        let (navy, cream) = (Color::new(0, 0, 80, 255), Color::new(250, 240, 220, 255));
+
+        let mut unchanged = kb.clone();
+        unchanged.quantize_colors(&[]);
+        assert_eq!(unchanged, kb);
+
+        kb.quantize_colors(&[navy, cream]);
+
+        let key_colors: Vec<_> = kb.keys.iter().map(|key| key.color).collect();
+        assert_eq!(key_colors, [navy, cream, navy]);
+
+        let legend_colors: Vec<_> = (kb.iter_legends())
+            .map(|(_, _, legend)| legend.color)
+            .collect();
+        assert_eq!(legend_colors, [cream, navy, cream, navy]);
+    }
+}
@@ -0,0 +1,436 @@
+//! Raster rendering of a [`Keyboard`], for use in thumbnails or previews.
+//!
+//! This module is only available with the `render` feature enabled.
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+use num_traits::real::Real;
+
+use crate::{utils::to_f64, Color, Key, Keyboard};
+
+// The number of samples per pixel along each axis used for anti-aliasing
+const SUPERSAMPLING: u32 = 4;
+
+// The inset of the legend area from the edge of the key in keyboard units, approximating KLE's
+const LEGEND_INSET: f64 = 0.15;
+
+// KLE renders keys at 54 px per unit and legends of the default size at 12 px
+const KLE_PX_PER_UNIT: f64 = 54.0;
+const KLE_DEFAULT_LEGEND_PX: f64 = 12.0;
+
+/// Options used by [`to_image`].
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// The number of pixels per keyboard unit. The default is 54, matching KLE.
+    pub pixels_per_unit: f64,
+    /// The margin around the layout in keyboard units. The default is 0.25.
+    pub margin: f64,
+    /// The corner radius of each key in keyboard units. The default is 0.1.
+    pub corner_radius: f64,
+    /// The font used to draw legends. Legends are not drawn if this is [`None`], which is the
+    /// default.
+    pub font: Option<FontArc>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            pixels_per_unit: KLE_PX_PER_UNIT,
+            margin: 0.25,
+            corner_radius: 0.1,
+            font: None,
+        }
+    }
+}
+
+// Maps between image pixels and a key's unrotated coordinate space, all in f64
+struct KeyTransform {
+    origin: (f64, f64),
+    px_per_unit: f64,
+    center: (f64, f64),
+    sin_cos: (f64, f64),
+}
+
+impl KeyTransform {
+    fn new<T>(key: &Key<T>, origin: (f64, f64), px_per_unit: f64) -> Self
+    where
+        T: Real,
+    {
+        Self {
+            origin,
+            px_per_unit,
+            center: (to_f64(key.rx), to_f64(key.ry)),
+            sin_cos: to_f64(key.rotation).to_radians().sin_cos(),
+        }
+    }
+
+    // Converts a point in the key's unrotated space to image pixels
+    fn to_image(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let ((cx, cy), (sin, cos)) = (self.center, self.sin_cos);
+        let (dx, dy) = (x - cx, y - cy);
+        let (x, y) = (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos);
+        (
+            (x - self.origin.0) * self.px_per_unit,
+            (y - self.origin.1) * self.px_per_unit,
+        )
+    }
+
+    // Converts a point in image pixels to the key's unrotated space
+    fn to_local(&self, (px, py): (f64, f64)) -> (f64, f64) {
+        let ((cx, cy), (sin, cos)) = (self.center, self.sin_cos);
+        let x = self.origin.0 + px / self.px_per_unit;
+        let y = self.origin.1 + py / self.px_per_unit;
+        let (dx, dy) = (x - cx, y - cy);
+        (cx + dx * cos + dy * sin, cy - dx * sin + dy * cos)
+    }
+
+    // Fills the region of the key's unrotated space within `bounds` (as min and max points), using
+    // `coverage` to determine the coverage of each point
+    fn fill<F>(
+        &self,
+        image: &mut RgbaImage,
+        bounds: ((f64, f64), (f64, f64)),
+        color: Color,
+        coverage: F,
+    ) where
+        F: Fn((f64, f64)) -> f64,
+    {
+        let ((x0, y0), (x1, y1)) = bounds;
+        let corners = [(x0, y0), (x1, y0), (x1, y1), (x0, y1)].map(|p| self.to_image(p));
+        let (min_x, min_y, max_x, max_y) = corners.iter().fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(min_x, min_y, max_x, max_y), &(x, y)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            },
+        );
+
+        // Clamped to the image, so the casts can't truncate
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let clamp = |v: f64, max: u32| v.max(0.0).min(f64::from(max)) as u32;
+        let (px0, px1) = (
+            clamp(min_x.floor(), image.width()),
+            clamp(max_x.ceil(), image.width()),
+        );
+        let (py0, py1) = (
+            clamp(min_y.floor(), image.height()),
+            clamp(max_y.ceil(), image.height()),
+        );
+
+        let step = 1.0 / f64::from(SUPERSAMPLING);
+        for py in py0..py1 {
+            for px in px0..px1 {
+                let total: f64 = (0..SUPERSAMPLING * SUPERSAMPLING)
+                    .map(|i| {
+                        let sx = f64::from(px) + (f64::from(i % SUPERSAMPLING) + 0.5) * step;
+                        let sy = f64::from(py) + (f64::from(i / SUPERSAMPLING) + 0.5) * step;
+                        coverage(self.to_local((sx, sy)))
+                    })
+                    .sum();
+                let coverage = total * step * step;
+                if coverage > 0.0 {
+                    blend(image.get_pixel_mut(px, py), color, coverage);
+                }
+            }
+        }
+    }
+}
+
+// Blends a colour over a pixel with the given coverage
+fn blend(pixel: &mut Rgba<u8>, color: Color, coverage: f64) {
+    let alpha = f64::from(color.a) / 255.0 * coverage.min(1.0);
+    let [r, g, b, a] = pixel.0;
+
+    // Both operands are in 0..=255, so the result is too
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let mix =
+        |src: u8, dst: u8| (f64::from(src) * alpha + f64::from(dst) * (1.0 - alpha)).round() as u8;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let a = (alpha * 255.0 + f64::from(a) * (1.0 - alpha)).round() as u8;
+
+    *pixel = Rgba([mix(color.r, r), mix(color.g, g), mix(color.b, b), a]);
+}
+
+// Returns whether a point is inside a rounded rectangle
+fn in_rounded_rect(
+    (x, y): (f64, f64),
+    ((x0, y0), (x1, y1)): ((f64, f64), (f64, f64)),
+    radius: f64,
+) -> bool {
+    let radius = radius.min((x1 - x0) / 2.0).min((y1 - y0) / 2.0).max(0.0);
+    let dx = (x0 + radius - x).max(x - (x1 - radius)).max(0.0);
+    let dy = (y0 + radius - y).max(y - (y1 - radius)).max(0.0);
+    x >= x0 && x <= x1 && y >= y0 && y <= y1 && dx * dx + dy * dy <= radius * radius
+}
+
+fn draw_key<T>(image: &mut RgbaImage, key: &Key<T>, transform: &KeyTransform, radius: f64)
+where
+    T: Real,
+{
+    let (x, y) = (to_f64(key.x), to_f64(key.y));
+    let rects = [
+        ((x, y), (x + to_f64(key.width), y + to_f64(key.height))),
+        (
+            (x + to_f64(key.x2), y + to_f64(key.y2)),
+            (
+                x + to_f64(key.x2) + to_f64(key.width2),
+                y + to_f64(key.y2) + to_f64(key.height2),
+            ),
+        ),
+    ];
+    let bounds = (
+        (
+            rects[0].0 .0.min(rects[1].0 .0),
+            rects[0].0 .1.min(rects[1].0 .1),
+        ),
+        (
+            rects[0].1 .0.max(rects[1].1 .0),
+            rects[0].1 .1.max(rects[1].1 .1),
+        ),
+    );
+
+    transform.fill(image, bounds, key.color, |point| {
+        let inside = rects
+            .iter()
+            .any(|&rect| in_rounded_rect(point, rect, radius));
+        if inside {
+            1.0
+        } else {
+            0.0
+        }
+    });
+}
+
+// Draws the legends on the top face of the key
+fn draw_legends<T>(image: &mut RgbaImage, key: &Key<T>, transform: &KeyTransform, font: &FontArc)
+where
+    T: Real,
+{
+    let ppu = transform.px_per_unit;
+    let (x0, y0) = (to_f64(key.x) + LEGEND_INSET, to_f64(key.y) + LEGEND_INSET);
+    let (x1, y1) = (
+        to_f64(key.x) + to_f64(key.width) - LEGEND_INSET,
+        to_f64(key.y) + to_f64(key.height) - LEGEND_INSET,
+    );
+
+    let grid = key.legend_grid();
+    for (row, legends) in grid.iter().take(3).enumerate() {
+        for (col, legend) in legends.iter().enumerate() {
+            let legend = match legend {
+                Some(legend) => legend,
+                None => continue,
+            };
+
+            let base_px = ppu * KLE_DEFAULT_LEGEND_PX / KLE_PX_PER_UNIT;
+            // Font sizes are far below f32's limits
+            #[allow(clippy::cast_possible_truncation)]
+            let scaled = font.as_scaled(PxScale::from(legend.size_px(base_px) as f32));
+
+            let ids: Vec<_> = legend.text.chars().map(|c| scaled.glyph_id(c)).collect();
+            let width: f32 = (ids.iter())
+                .zip(ids.iter().skip(1).map(Some).chain([None]))
+                .map(|(&id, next)| scaled.h_advance(id) + next.map_or(0.0, |&n| scaled.kern(id, n)))
+                .sum();
+            let (ascent, descent) = (scaled.ascent(), scaled.descent());
+
+            // Position the text within the legend area in pixels relative to its top left
+            let (area_w, area_h) = ((x1 - x0) * ppu, (y1 - y0) * ppu);
+            let left = match col {
+                0 => 0.0,
+                1 => (area_w - f64::from(width)) / 2.0,
+                _ => area_w - f64::from(width),
+            };
+            let baseline = match row {
+                0 => f64::from(ascent),
+                1 => (area_h + f64::from(ascent + descent)) / 2.0,
+                _ => area_h + f64::from(descent),
+            };
+
+            // Rasterise the legend into a coverage buffer in the key's unrotated space
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let (buf_w, buf_h) = (
+                area_w.ceil().max(0.0) as usize,
+                area_h.ceil().max(0.0) as usize,
+            );
+            let mut buffer = vec![0.0_f32; buf_w * buf_h];
+            #[allow(clippy::cast_possible_truncation)]
+            let mut caret = point(left as f32, baseline as f32);
+            for (i, &id) in ids.iter().enumerate() {
+                if let Some(outline) =
+                    scaled.outline_glyph(id.with_scale_and_position(scaled.scale(), caret))
+                {
+                    let bounds = outline.px_bounds();
+                    outline.draw(|gx, gy, c| {
+                        // Glyph offsets are small, so there's no loss of precision
+                        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+                        let (bx, by) = (
+                            (bounds.min.x + gx as f32) as isize,
+                            (bounds.min.y + gy as f32) as isize,
+                        );
+                        if let (Ok(bx), Ok(by)) = (usize::try_from(bx), usize::try_from(by)) {
+                            if bx < buf_w && by < buf_h {
+                                buffer[by * buf_w + bx] += c;
+                            }
+                        }
+                    });
+                }
+                caret.x +=
+                    scaled.h_advance(id) + ids.get(i + 1).map_or(0.0, |&n| scaled.kern(id, n));
+            }
+
+            transform.fill(image, ((x0, y0), (x1, y1)), legend.color, |(x, y)| {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let (bx, by) = (((x - x0) * ppu) as usize, ((y - y0) * ppu) as usize);
+                if x >= x0 && y >= y0 && bx < buf_w && by < buf_h {
+                    f64::from(buffer[by * buf_w + bx].min(1.0))
+                } else {
+                    0.0
+                }
+            });
+        }
+    }
+}
+
+/// Renders the keyboard to an image.
+///
+/// Keys are drawn as rounded rectangles in their colour, taking rotation and stepped or L-shaped
+/// keys into account, on the layout's background colour. If [`RenderOptions::font`] is set, the
+/// legends on the top face of each key are drawn using it; front legends are not drawn. Decals
+/// are drawn without a key, so only their legends are visible.
+///
+/// The image covers the layout's [`bounding box`](Keyboard::bounding_box_including_decals) plus
+/// [`RenderOptions::margin`] on each side. This is intended for generating thumbnails and previews
+/// rather than an exact reproduction of KLE's rendering.
+#[must_use]
+pub fn to_image<T>(keyboard: &Keyboard<T>, options: &RenderOptions) -> RgbaImage
+where
+    T: Real,
+{
+    let ppu = options.pixels_per_unit;
+    let (x, y, width, height) =
+        keyboard
+            .bounding_box_including_decals()
+            .map_or((0.0, 0.0, 0.0, 0.0), |rect| {
+                (
+                    to_f64(rect.x),
+                    to_f64(rect.y),
+                    to_f64(rect.width),
+                    to_f64(rect.height),
+                )
+            });
+    let origin = (x - options.margin, y - options.margin);
+
+    // Image sizes are always positive and reasonably small
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let size = |len: f64| ((len + 2.0 * options.margin) * ppu).ceil().max(0.0) as u32;
+
+    let background = keyboard.metadata.background_color;
+    let mut image = RgbaImage::from_pixel(
+        size(width),
+        size(height),
+        Rgba([background.r, background.g, background.b, background.a]),
+    );
+
+    for key in &keyboard.keys {
+        let transform = KeyTransform::new(key, origin, ppu);
+        if !key.decal {
+            draw_key(&mut image, key, &transform, options.corner_radius);
+        }
+        if let Some(ref font) = options.font {
+            draw_legends(&mut image, key, &transform, font);
+        }
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_image() {
+        let kb: Keyboard = serde_json::from_str(
+            r##"[
+                {"backcolor": "#ffffff"},
+                [{"c": "#ff0000"}, "A", {"c": "#0000ff", "w": 2}, "B"],
+                [{"r": 45, "rx": 1, "ry": 3, "c": "#00ff00"}, "C"]
+            ]"##,
+        )
+        .unwrap();
+        let options = RenderOptions {
+            pixels_per_unit: 10.0,
+            margin: 0.5,
+            ..RenderOptions::default()
+        };
+        let image = to_image(&kb, &options);
+
+        // The layout is 3 by 3 + sqrt(2) units, plus the margins
+        assert_eq!((image.width(), image.height()), (40, 55));
+
+        // Returns the pixel at a position in the layout
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let px = |x: f64, y: f64| {
+            image
+                .get_pixel(((x + 0.5) * 10.0) as u32, ((y + 0.5) * 10.0) as u32)
+                .0
+        };
+
+        assert_eq!(px(0.5, 0.5), [255, 0, 0, 255]);
+        assert_eq!(px(2.0, 0.5), [0, 0, 255, 255]);
+        assert_eq!(px(0.5, 1.5), [255, 255, 255, 255]);
+
+        // The rotated key's centre is below its centre of rotation
+        let (cx, cy) = kb.keys[2].center();
+        assert_eq!(px(cx, cy), [0, 255, 0, 255]);
+
+        // The corners of keys are rounded, so the corner pixel is only partially covered
+        assert_ne!(px(0.01, 0.01), [255, 0, 0, 255]);
+        assert_ne!(px(0.01, 0.01), [255, 255, 255, 255]);
+
+        let image = to_image(&<Keyboard>::default(), &options);
+        assert_eq!((image.width(), image.height()), (10, 10));
+    }
+
+    #[test]
+    fn test_to_image_legends() {
+        // A minimal font containing only a glyph for "A"
+        let font = FontArc::try_from_slice(include_bytes!("../tests/fonts/demo.ttf")).unwrap();
+
+        let kb: Keyboard = serde_json::from_str(
+            r##"[
+                {"backcolor": "#ffffff"},
+                [{"c": "#ffffff", "t": "#000000", "f": 9}, "A"]
+            ]"##,
+        )
+        .unwrap();
+        let options = RenderOptions {
+            pixels_per_unit: 54.0,
+            margin: 0.0,
+            font: Some(font),
+            ..RenderOptions::default()
+        };
+
+        // Counts the pixels darker than mid grey in the top and bottom halves of the key
+        let dark_pixels = |image: &RgbaImage| {
+            let mut count = [0, 0];
+            for (_, y, pixel) in image.enumerate_pixels() {
+                if pixel.0[0] < 128 {
+                    count[usize::from(y >= 27)] += 1;
+                }
+            }
+            count
+        };
+
+        // The legend is drawn in the top left, so all of its pixels are in the top half
+        let [top, bottom] = dark_pixels(&to_image(&kb, &options));
+        assert!(top > 0);
+        assert_eq!(bottom, 0);
+
+        // Without a font nothing is drawn
+        let options = RenderOptions {
+            font: None,
+            ..options
+        };
+        assert_eq!(dark_pixels(&to_image(&kb, &options)), [0, 0]);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::Legend;
+
+/// The formatting applied to a [`TextRun::Text`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct TextStyle {
+    /// Whether the text is bold, set by `<b>` or `<strong>`.
+    pub bold: bool,
+    /// Whether the text is italic, set by `<i>` or `<em>`.
+    pub italic: bool,
+    /// Whether the text is underlined, set by `<u>`.
+    pub underline: bool,
+    /// Whether the text is struck through, set by `<s>`, `<strike>`, or `<del>`.
+    pub strikethrough: bool,
+}
+
+/// A run of a legend's rich text as returned by [`Legend::rich_runs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextRun {
+    /// Text with the same formatting throughout. HTML entities are already decoded.
+    Text {
+        /// The text of the run.
+        text: String,
+        /// The formatting of the run.
+        style: TextStyle,
+    },
+    /// An icon, written in KLE as an `<i>` element with a `class` attribute. For example,
+    /// `<i class='fa fa-windows'></i>` is a Font Awesome icon, and
+    /// `<i class='kb kb-Multimedia-Play'></i>` is one of KLE's keyboard icons.
+    Icon {
+        /// The icon's class attribute.
+        class: String,
+    },
+    /// A line break, written as `<br>`.
+    LineBreak,
+}
+
+// The effect of an open tag, which is undone when the tag is closed
+#[derive(Debug, Clone, Copy)]
+enum Tag {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Other,
+}
+
+impl Tag {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "b" | "strong" => Self::Bold,
+            "i" | "em" => Self::Italic,
+            "u" => Self::Underline,
+            "s" | "strike" | "del" => Self::Strikethrough,
+            _ => Self::Other,
+        }
+    }
+}
+
+// A parsed HTML tag
+struct ParsedTag<'a> {
+    name: String,
+    closing: bool,
+    attributes: &'a str,
+}
+
+// Parses a tag at the start of the string, returning the tag and the length of the input
+// consumed. Anything which doesn't look like a tag is treated as text, as browsers do
+fn parse_tag(input: &str) -> Option<(ParsedTag<'_>, usize)> {
+    let inner = input.strip_prefix('<')?;
+    let end = inner.find('>')?;
+    let inner = &inner[..end];
+
+    let (closing, inner) = match inner.strip_prefix('/') {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+    if !inner.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let name_len = inner
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(inner.len());
+    let tag = ParsedTag {
+        name: inner[..name_len].to_ascii_lowercase(),
+        closing,
+        attributes: inner[name_len..].trim_end_matches('/'),
+    };
+    Some((tag, end + 2))
+}
+
+// Returns the value of the given attribute, which may be quoted with either kind of quote or
+// unquoted
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attributes;
+    loop {
+        rest = rest.trim_start();
+        let name_len = rest.find(|c: char| c == '=' || c.is_whitespace())?;
+        let (attr, after) = rest.split_at(name_len);
+        let after = after.trim_start();
+
+        let after = if let Some(after) = after.strip_prefix('=') {
+            after.trim_start()
+        } else {
+            // An attribute without a value
+            rest = after;
+            continue;
+        };
+        let (value, remaining) = if let Some(quote @ ('"' | '\'')) = after.chars().next() {
+            let after = &after[1..];
+            let end = after.find(quote).unwrap_or(after.len());
+            (&after[..end], after.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = after.find(char::is_whitespace).unwrap_or(after.len());
+            after.split_at(end)
+        };
+
+        if attr.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        rest = remaining;
+    }
+}
+
+// Decodes an HTML entity at the start of the string, returning the character and the length of
+// the input consumed
+fn parse_entity(input: &str) -> Option<(char, usize)> {
+    let end = input.get(..12).unwrap_or(input).find(';')?;
+    let name = &input[1..end];
+
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(&['x', 'X'][..]) {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => code.parse(),
+            };
+            char::from_u32(code.ok()?)?
+        }
+    };
+    Some((c, end + 1))
+}
+
+// Builds up the list of runs, merging adjacent text with the same style
+#[derive(Default)]
+struct RunBuilder {
+    runs: Vec<TextRun>,
+    text: String,
+    style: TextStyle,
+}
+
+impl RunBuilder {
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            self.runs.push(TextRun::Text {
+                text: std::mem::take(&mut self.text),
+                style: self.style,
+            });
+        }
+    }
+
+    fn set_style(&mut self, style: TextStyle) {
+        if style != self.style {
+            self.flush();
+            self.style = style;
+        }
+    }
+
+    fn push_run(&mut self, run: TextRun) {
+        self.flush();
+        self.runs.push(run);
+    }
+
+    fn finish(mut self) -> Vec<TextRun> {
+        self.flush();
+        self.runs
+    }
+}
+
+// Returns the style resulting from the given open tags
+fn style_of(open: &[Tag]) -> TextStyle {
+    open.iter()
+        .fold(TextStyle::default(), |style, tag| match tag {
+            Tag::Bold => TextStyle {
+                bold: true,
+                ..style
+            },
+            Tag::Italic => TextStyle {
+                italic: true,
+                ..style
+            },
+            Tag::Underline => TextStyle {
+                underline: true,
+                ..style
+            },
+            Tag::Strikethrough => TextStyle {
+                strikethrough: true,
+                ..style
+            },
+            Tag::Other => style,
+        })
+}
+
+impl Legend {
+    /// Parses the legend's text as HTML, returning a sequence of formatted runs.
+    ///
+    /// KLE renders legends as HTML, and layouts commonly use a small subset of it for formatting.
+    /// This function recognises bold (`<b>`, `<strong>`), italic (`<i>`, `<em>`), underline
+    /// (`<u>`), and strikethrough (`<s>`, `<strike>`, `<del>`) tags, icons written as `<i>`
+    /// elements with a `class` attribute, and line breaks (`<br>`). The common named HTML
+    /// entities and numeric character references are decoded. Any other tags are removed, but
+    /// their contents are kept.
+    ///
+    /// Like a browser, the parser never fails. Unclosed tags apply until the end of the legend,
+    /// closing tags without a matching open tag are ignored, and a closing tag also closes any
+    /// tags opened inside it which haven't been closed yet. A `<` which does not start a tag and
+    /// an unrecognised entity are treated as text.
+    ///
+    /// The legend's [`text`](Legend::text) is not modified. Adjacent text with the same formatting
+    /// is merged into a single run, and empty runs are omitted.
+    #[must_use]
+    pub fn rich_runs(&self) -> Vec<TextRun> {
+        let mut builder = RunBuilder::default();
+        // Each open tag, along with its name so it can be matched with its closing tag
+        let mut open: Vec<(String, Tag)> = Vec::new();
+        let mut rest = self.text.as_str();
+
+        while let Some(c) = rest.chars().next() {
+            if c == '<' {
+                if let Some((tag, len)) = parse_tag(rest) {
+                    rest = &rest[len..];
+                    if tag.closing {
+                        if let Some(i) = open.iter().rposition(|(name, _)| *name == tag.name) {
+                            open.truncate(i);
+                        }
+                    } else if tag.name == "br" {
+                        builder.push_run(TextRun::LineBreak);
+                    } else if let Some(class) = (tag.name == "i")
+                        .then(|| attribute(tag.attributes, "class"))
+                        .flatten()
+                    {
+                        builder.push_run(TextRun::Icon {
+                            class: class.trim().into(),
+                        });
+                        open.push((tag.name, Tag::Other));
+                    } else if !matches!(tag.name.as_str(), "img" | "hr" | "wbr") {
+                        // Void elements have no closing tag, so aren't added to the stack
+                        let kind = Tag::from_name(&tag.name);
+                        open.push((tag.name, kind));
+                    }
+
+                    let tags: Vec<_> = open.iter().map(|&(_, tag)| tag).collect();
+                    builder.set_style(style_of(&tags));
+                    continue;
+                }
+            } else if c == '&' {
+                if let Some((c, len)) = parse_entity(rest) {
+                    builder.text.push(c);
+                    rest = &rest[len..];
+                    continue;
+                }
+            }
+
+            builder.text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        builder.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legend(text: &str) -> Legend {
+        Legend {
+            text: text.into(),
+            ..Legend::default()
+        }
+    }
+
+    fn text(text: &str, style: TextStyle) -> TextRun {
+        TextRun::Text {
+            text: text.into(),
+            style,
+        }
+    }
+
+    const BOLD: TextStyle = TextStyle {
+        bold: true,
+        italic: false,
+        underline: false,
+        strikethrough: false,
+    };
+    const ITALIC: TextStyle = TextStyle {
+        bold: false,
+        italic: true,
+        underline: false,
+        strikethrough: false,
+    };
+    const PLAIN: TextStyle = TextStyle {
+        bold: false,
+        italic: false,
+        underline: false,
+        strikethrough: false,
+    };
+
+    #[test]
+    fn test_parse_tag() {
+        let (tag, len) = parse_tag("<B class='x'>rest").unwrap();
+        assert_eq!(tag.name, "b");
+        assert!(!tag.closing);
+        assert_eq!(tag.attributes, " class='x'");
+        assert_eq!(len, 13);
+
+        let (tag, len) = parse_tag("</i>").unwrap();
+        assert_eq!(tag.name, "i");
+        assert!(tag.closing);
+        assert_eq!(len, 4);
+
+        let (tag, _) = parse_tag("<br/>").unwrap();
+        assert_eq!(tag.name, "br");
+
+        assert!(parse_tag("< b>").is_none());
+        assert!(parse_tag("<3").is_none());
+        assert!(parse_tag("<=>").is_none());
+    }
+
+    #[test]
+    fn test_attribute() {
+        assert_eq!(attribute(" class='fa fa-x'", "class"), Some("fa fa-x"));
+        assert_eq!(attribute(r#" id="a" CLASS="kb""#, "class"), Some("kb"));
+        assert_eq!(attribute(" hidden class=icon", "class"), Some("icon"));
+        assert_eq!(attribute(" id='a'", "class"), None);
+        assert_eq!(attribute("", "class"), None);
+    }
+
+    #[test]
+    fn test_parse_entity() {
+        assert_eq!(parse_entity("&amp;rest"), Some(('&', 5)));
+        assert_eq!(parse_entity("&#169;"), Some(('©', 6)));
+        assert_eq!(parse_entity("&#x2318;"), Some(('⌘', 8)));
+        assert_eq!(parse_entity("&unknown;"), None);
+        assert_eq!(parse_entity("& no"), None);
+        assert_eq!(parse_entity("&#xffffffff;"), None);
+    }
+
+    #[test]
+    fn test_legend_rich_runs() {
+        let bold_italic = legend("<b>Bold</b> and <i>italic</i>");
+        assert_eq!(
+            bold_italic.rich_runs(),
+            [
+                text("Bold", BOLD),
+                text(" and ", PLAIN),
+                text("italic", ITALIC)
+            ]
+        );
+        assert_eq!(bold_italic.text, "<b>Bold</b> and <i>italic</i>");
+
+        let runs = legend("<i class='kb kb-Multimedia-Play'></i><br>Play&nbsp;&amp;<BR/>Pause")
+            .rich_runs();
+        assert_eq!(
+            runs,
+            [
+                TextRun::Icon {
+                    class: "kb kb-Multimedia-Play".into()
+                },
+                TextRun::LineBreak,
+                text("Play\u{a0}&", PLAIN),
+                TextRun::LineBreak,
+                text("Pause", PLAIN),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_legend_rich_runs_malformed() {
+        // Nested, misnested, and unclosed tags
+        let runs = legend("<b>a<i>b</b>c<u>d</strong>e<span>f").rich_runs();
+        let underline = TextStyle {
+            underline: true,
+            ..PLAIN
+        };
+        assert_eq!(
+            runs,
+            [
+                text("a", BOLD),
+                text(
+                    "b",
+                    TextStyle {
+                        bold: true,
+                        italic: true,
+                        ..PLAIN
+                    }
+                ),
+                text("c", PLAIN),
+                text("def", underline),
+            ]
+        );
+
+        // Things which aren't tags or entities are kept as text
+        let runs = legend("1 < 2 &foo; <3 & <").rich_runs();
+        assert_eq!(runs, [text("1 < 2 &foo; <3 & <", PLAIN)]);
+
+        assert!(legend("").rich_runs().is_empty());
+        assert!(legend("<b></b><img src='x'>").rich_runs().is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+/// Estimates the number of keys in a KLE JSON file without deserialising it.
+///
+/// This performs a single fast pass over the input, counting string values whose parent is an
+/// array nested directly inside another array, which is where KLE stores each key's legends. It
+/// does not validate or deserialise anything, making it suitable for progress reporting before
+/// deserialising a large file.
+///
+/// For valid KLE JSON the result is typically exact, but it should be treated as an estimate since
+/// the input is not validated. Layouts embedded in another object (see
+/// [`DeserializeOptions::wrapper_key`](crate::DeserializeOptions::wrapper_key)) are also supported,
+/// but any other nested arrays of strings will be counted too.
+#[must_use]
+pub fn estimate_key_count(bytes: &[u8]) -> usize {
+    // We only need to know whether the current container and its parent are arrays, but we keep
+    // a stack of the outer containers so we can restore them when leaving the current container
+    let mut is_array = [false; 2]; // [parent, current]
+    let mut outer = Vec::new();
+
+    let mut count = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for &byte in bytes {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => {
+                in_string = true;
+                if is_array == [true, true] {
+                    count += 1;
+                }
+            }
+            b'[' | b'{' => {
+                outer.push(is_array[0]);
+                is_array = [is_array[1], byte == b'['];
+            }
+            b']' | b'}' => {
+                if let Some(grandparent) = outer.pop() {
+                    is_array = [grandparent, is_array[0]];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Keyboard;
+
+    use super::*;
+
+    #[test]
+    fn test_estimate_key_count() {
+        let json = r#"[
+            {"name": "test \"quoted\" [name]", "notes": "\\"},
+            ["A", {"a": 4, "p": "DSA"}, "B\n\"[C]\"", ""],
+            [{"t": "\\\"", "fa": [1, 2]}, "D"],
+            [],
+            ["E", "F"]
+        ]"#;
+        assert_eq!(estimate_key_count(json.as_bytes()), 6);
+
+        let json = "[[\"A\", \"B\"], [\"C\"]]";
+        let kb: Keyboard = serde_json::from_str(json).unwrap();
+        assert_eq!(estimate_key_count(json.as_bytes()), kb.keys.len());
+
+        let json = r#"{"name": "wrapped", "layout": [["A", "B"], ["C"]]}"#;
+        assert_eq!(estimate_key_count(json.as_bytes()), 3);
+
+        assert_eq!(estimate_key_count(b""), 0);
+        assert_eq!(estimate_key_count(b"]]\"[[\"A"), 0);
+    }
+}
@@ -0,0 +1,358 @@
+use num_traits::real::Real;
+use serde::{ser::SerializeSeq, Serialize, Serializer};
+
+use crate::Color;
+
+pub(crate) fn color_to_string(color: Color) -> String {
+    let Color { r, g, b, a } = color;
+    if a == u8::MAX {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+    }
+}
+
+// We only use this with skip_serializing_if = "Option::is_none", but serde requires the signature
+// to match the field type
+#[allow(clippy::ref_option, clippy::trivially_copy_pass_by_ref)]
+fn ser_color<S>(color: &Option<Color>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    color.map(color_to_string).serialize(serializer)
+}
+
+// KLE colour arrays are just \n delimited strings, so we join them back together here
+#[allow(clippy::ref_option)]
+fn ser_nl_delimited_colors<S>(
+    colors: &Option<Vec<Option<Color>>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    colors
+        .as_ref()
+        .map(|colors| {
+            let colors: Vec<_> = colors
+                .iter()
+                .map(|c| c.map(color_to_string).unwrap_or_default())
+                .collect();
+            colors.join("\n")
+        })
+        .serialize(serializer)
+}
+
+// KLE writes whole numbers without a fractional part, so we do the same rather than using T's
+// Serialize implementation, which may output e.g. 2.0 for floats. Serde requires the signature to
+// match the field type
+#[allow(clippy::ref_option)]
+fn ser_number<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Real + Serialize,
+{
+    match value
+        .filter(|value| value.fract().is_zero())
+        .and_then(|value| value.to_i64())
+    {
+        Some(value) => serializer.serialize_i64(value),
+        None => value.serialize(serializer),
+    }
+}
+
+#[derive(Serialize, Default, Debug, Clone)]
+pub(crate) struct KleBackground {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+}
+
+// Fields are in the same order KLE uses when serialising
+#[derive(Serialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct KleMetadata {
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_color")]
+    pub backcolor: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<KleBackground>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radii: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch_mount: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch_brand: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pcb: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plate: Option<bool>,
+}
+
+// Fields are in the same order KLE uses when serialising
+#[derive(Serialize, Debug, Clone)]
+#[serde(bound(serialize = "T: Serialize"))]
+pub(crate) struct KlePropsObject<T = f64>
+where
+    T: Real,
+{
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub r: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub rx: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub ry: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub y: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub x: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_color")]
+    pub c: Option<Color>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_nl_delimited_colors"
+    )]
+    pub t: Option<Vec<Option<Color>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub g: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sb: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub st: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub a: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub f: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub f2: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fa: Option<Vec<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub w: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub h: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub w2: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub h2: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub x2: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "ser_number")]
+    pub y2: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub d: Option<bool>,
+}
+
+// Can't derive Default unless we add T: Default trait bound
+impl<T> Default for KlePropsObject<T>
+where
+    T: Real,
+{
+    fn default() -> Self {
+        Self {
+            r: None,
+            rx: None,
+            ry: None,
+            y: None,
+            x: None,
+            c: None,
+            t: None,
+            g: None,
+            p: None,
+            sm: None,
+            sb: None,
+            st: None,
+            a: None,
+            f: None,
+            f2: None,
+            fa: None,
+            w: None,
+            h: None,
+            w2: None,
+            h2: None,
+            x2: None,
+            y2: None,
+            n: None,
+            l: None,
+            d: None,
+        }
+    }
+}
+
+impl<T> KlePropsObject<T>
+where
+    T: Real,
+{
+    pub fn is_empty(&self) -> bool {
+        let Self {
+            r,
+            rx,
+            ry,
+            y,
+            x,
+            c,
+            t,
+            g,
+            p,
+            sm,
+            sb,
+            st,
+            a,
+            f,
+            f2,
+            fa,
+            w,
+            h,
+            w2,
+            h2,
+            x2,
+            y2,
+            n,
+            l,
+            d,
+        } = self;
+
+        [r, rx, ry, y, x, w, h, w2, h2, x2, y2]
+            .iter()
+            .all(|v| v.is_none())
+            && [g, n, l, d].iter().all(|v| v.is_none())
+            && [p, sm, sb, st].iter().all(|v| v.is_none())
+            && [a, f, f2].iter().all(|v| v.is_none())
+            && c.is_none()
+            && t.is_none()
+            && fa.is_none()
+    }
+}
+
+// Represents either a key or a JSON object containing properties for the next key(s)
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum KleLegendsOrProps<T = f64>
+where
+    T: Real,
+{
+    Props(Box<KlePropsObject<T>>),
+    Legend(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct KleKeyboard<T = f64>
+where
+    T: Real,
+{
+    pub meta: Option<KleMetadata>,
+    pub layout: Vec<Vec<KleLegendsOrProps<T>>>,
+}
+
+impl<T> Serialize for KleKeyboard<T>
+where
+    T: Real + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let len = usize::from(self.meta.is_some()) + self.layout.len();
+        let mut seq = serializer.serialize_seq(Some(len))?;
+
+        if let Some(ref meta) = self.meta {
+            seq.serialize_element(meta)?;
+        }
+        for row in &self.layout {
+            seq.serialize_element(row)?;
+        }
+
+        seq.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_to_string() {
+        assert_eq!(color_to_string(Color::new(255, 0, 153, 255)), "#ff0099");
+        assert_eq!(color_to_string(Color::new(255, 0, 153, 204)), "#ff0099cc");
+    }
+
+    #[test]
+    fn test_kle_props_object_is_empty() {
+        assert!(KlePropsObject::<f64>::default().is_empty());
+        assert!(!KlePropsObject::<f64> {
+            fa: Some(vec![]),
+            ..KlePropsObject::default()
+        }
+        .is_empty());
+    }
+
+    #[test]
+    fn test_ser_number() {
+        let props = KlePropsObject::<f64> {
+            y: Some(0.25),
+            x: Some(-1.0),
+            w: Some(2.0),
+            ..KlePropsObject::default()
+        };
+        let json = serde_json::to_string(&props).unwrap();
+        assert_eq!(json, r#"{"y":0.25,"x":-1,"w":2}"#);
+
+        let props = KlePropsObject::<f32> {
+            r: Some(15.0),
+            h: Some(0.1),
+            ..KlePropsObject::default()
+        };
+        let json = serde_json::to_string(&props).unwrap();
+        assert_eq!(json, r#"{"r":15,"h":0.1}"#);
+    }
+
+    #[test]
+    fn test_serialize_kle_keyboard() {
+        let kb = KleKeyboard::<f64> {
+            meta: Some(KleMetadata {
+                name: Some("test".into()),
+                backcolor: Some(Color::new(204, 34, 34, 255)),
+                ..KleMetadata::default()
+            }),
+            layout: vec![
+                vec![
+                    KleLegendsOrProps::Props(Box::new(KlePropsObject {
+                        w: Some(2.0),
+                        t: Some(vec![Some(Color::new(255, 0, 0, 255)), None]),
+                        ..KlePropsObject::default()
+                    })),
+                    KleLegendsOrProps::Legend("A".into()),
+                ],
+                vec![KleLegendsOrProps::Legend("B".into())],
+            ],
+        };
+
+        assert_eq!(
+            serde_json::to_string(&kb).unwrap(),
+            r##"[{"backcolor":"#cc2222","name":"test"},[{"t":"#ff0000\n","w":2},"A"],["B"]]"##
+        );
+
+        let kb = KleKeyboard::<f64> {
+            meta: None,
+            layout: vec![vec![KleLegendsOrProps::Legend("A".into())]],
+        };
+
+        assert_eq!(serde_json::to_string(&kb).unwrap(), r#"[["A"]]"#);
+    }
+}
@@ -0,0 +1,547 @@
+mod json;
+
+use crate::{
+    color,
+    utils::{unalign_legends, Alignment, FontSize},
+    Background, Color, Key, Keyboard, Legend, Metadata, NUM_LEGENDS,
+};
+use json::{KleBackground, KleLegendsOrProps, KleMetadata, KlePropsObject};
+
+pub(crate) use json::KleKeyboard;
+use num_traits::real::Real;
+
+// Returns Some(value) if it's different to the default
+fn non_default<V>(value: &V, default: &V) -> Option<V>
+where
+    V: PartialEq + Clone,
+{
+    (value != default).then(|| value.clone())
+}
+
+impl From<&Background> for KleBackground {
+    fn from(value: &Background) -> Self {
+        let default = Background::default();
+        Self {
+            name: non_default(&value.name, &default.name),
+            style: non_default(&value.style, &default.style),
+        }
+    }
+}
+
+impl From<&Metadata> for KleMetadata {
+    fn from(value: &Metadata) -> Self {
+        let default = Metadata::default();
+        let (switch_mount, switch_brand, switch_type) = value.key_switch.to_kle_fields();
+
+        Self {
+            backcolor: non_default(&value.background_color, &default.background_color),
+            name: non_default(&value.name, &default.name),
+            author: non_default(&value.author, &default.author),
+            notes: non_default(&value.notes, &default.notes),
+            background: (value.background != default.background)
+                .then(|| KleBackground::from(&value.background)),
+            radii: non_default(&value.radii, &default.radii),
+            switch_mount,
+            switch_brand,
+            switch_type,
+            pcb: non_default(&value.pcb_mount, &default.pcb_mount),
+            plate: non_default(&value.plate_mount, &default.plate_mount),
+        }
+    }
+}
+
+// The running state of the serialiser. This mirrors the deserialiser's state so we only emit the
+// properties that differ from what the deserialiser would otherwise use
+#[derive(Debug, Clone)]
+struct KleProps<T = f64>
+where
+    T: Real,
+{
+    x: T,
+    y: T,
+    r: T,
+    rx: T,
+    ry: T,
+    g: bool,                  // ghosted
+    sm: String,               // switch mount
+    sb: String,               // switch brand
+    st: String,               // switch type
+    c: Color,                 // color
+    ta: [Color; NUM_LEGENDS], // legend color array
+    a: Alignment,             // alignment
+    p: String,                // profile
+    f: usize,                 // fallback font size
+    fa: [usize; NUM_LEGENDS], // font size array
+}
+
+impl<T> Default for KleProps<T>
+where
+    T: Real,
+{
+    fn default() -> Self {
+        Self {
+            x: T::zero(),
+            y: T::zero(),
+            r: T::zero(),
+            rx: T::zero(),
+            ry: T::zero(),
+            g: false,
+            sm: String::new(),
+            sb: String::new(),
+            st: String::new(),
+            c: color::KEY,
+            ta: [color::LEGEND; NUM_LEGENDS],
+            a: Alignment::default(),
+            p: String::new(),
+            f: usize::from(FontSize::default()),
+            fa: [usize::from(FontSize::default()); NUM_LEGENDS],
+        }
+    }
+}
+
+// Joins the legends into KLE's newline delimited format, trimming any trailing empty legends
+fn legend_string(legends: &[Option<&Legend>]) -> String {
+    let len = legends
+        .iter()
+        .rposition(Option::is_some)
+        .map_or(0, |i| i + 1);
+    let texts: Vec<_> = legends[..len]
+        .iter()
+        .map(|legend| legend.map_or("", |legend| legend.text.as_str()))
+        .collect();
+    texts.join("\n")
+}
+
+// Chooses the alignment that results in the shortest legend string, preferring the given
+// alignment and then KLE's default where possible
+fn best_alignment<T>(
+    key: &Key<T>,
+    preferred: Alignment,
+) -> (Alignment, [Option<&Legend>; NUM_LEGENDS])
+where
+    T: Real,
+{
+    let candidates = [usize::from(preferred), usize::from(Alignment::default())]
+        .into_iter()
+        .chain(0..NUM_ALIGNMENTS)
+        .filter_map(|a| Alignment::new(a).ok());
+
+    candidates
+        .map(|a| (a, unalign_legends(&key.legends, a)))
+        .min_by_key(|(_, legends)| legend_string(legends).len())
+        .unwrap_or_else(|| unreachable!("there is always at least one valid alignment"))
+}
+
+impl<T> KleProps<T>
+where
+    T: Real,
+{
+    fn text_colors(
+        &mut self,
+        legends: &[Option<&Legend>; NUM_LEGENDS],
+    ) -> Option<Vec<Option<Color>>> {
+        let populated: Vec<_> = (legends.iter().enumerate())
+            .filter_map(|(i, l)| l.map(|l| (i, l.color)))
+            .collect();
+
+        if populated.iter().all(|&(i, c)| self.ta[i] == c) {
+            return None;
+        }
+
+        // The first colour is used as the default for any other legends, so use the first legend's
+        // colour if it's set, otherwise use the most common one
+        let default = legends[0].map_or_else(
+            || {
+                let count = |color| populated.iter().filter(|&&(_, c)| c == color).count();
+                (populated.iter().map(|&(_, c)| c))
+                    .fold(None, |best: Option<Color>, c| match best {
+                        Some(b) if count(b) >= count(c) => Some(b),
+                        _ => Some(c),
+                    })
+                    .unwrap_or(color::LEGEND)
+            },
+            |l| l.color,
+        );
+
+        let mut colors = vec![None; NUM_LEGENDS];
+        colors[0] = Some(default);
+        for &(i, c) in &populated {
+            if c != default {
+                colors[i] = Some(c);
+            }
+        }
+        let len = colors
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |i| i + 1);
+        colors.truncate(len);
+
+        self.ta = std::array::from_fn(|i| colors.get(i).copied().flatten().unwrap_or(default));
+
+        Some(colors)
+    }
+
+    // Returns the f, f2, and fa properties needed to get the legends' font sizes
+    fn font_sizes(
+        &mut self,
+        legends: &[Option<&Legend>; NUM_LEGENDS],
+    ) -> (Option<usize>, Option<usize>, Option<Vec<usize>>) {
+        let populated: Vec<_> = (legends.iter().enumerate())
+            .filter_map(|(i, l)| l.map(|l| (i, l.size)))
+            .collect();
+
+        if populated.iter().all(|&(i, s)| self.fa[i] == s) {
+            return (None, None, None);
+        }
+
+        let first = populated[0].1;
+        let rest: Vec<_> = populated.iter().filter(|&&(i, _)| i > 0).collect();
+
+        if populated.iter().all(|&(_, s)| s == first) {
+            // All sizes are the same, so we can just use f
+            self.f = first;
+            self.fa = [first; NUM_LEGENDS];
+            (Some(first), None, None)
+        } else if rest.iter().all(|&&(_, s)| s == rest[0].1) {
+            // All sizes except the first are the same, so we can use f and f2
+            let f = legends[0].map_or(self.f, |l| l.size);
+            let f2 = rest[0].1;
+            let f_prop = (f != self.f).then_some(f);
+            self.f = f;
+            self.fa = std::array::from_fn(|i| if i == 0 { f } else { f2 });
+            (f_prop, Some(f2), None)
+        } else {
+            let len = legends
+                .iter()
+                .rposition(Option::is_some)
+                .map_or(0, |i| i + 1);
+            let fa: Vec<_> = legends[..len]
+                .iter()
+                .map(|l| l.map_or(0, |l| l.size))
+                .collect();
+            self.fa = std::array::from_fn(|i| match fa.get(i).copied() {
+                Some(s) if s > 0 => s,
+                _ => self.f,
+            });
+            (None, None, Some(fa))
+        }
+    }
+
+    // Variable names match KLE's property names
+    #[allow(clippy::many_single_char_names)]
+    fn serialize_key(&mut self, key: &Key<T>) -> (KlePropsObject<T>, String) {
+        // Rotation properties can only change at the start of a row, and just like the
+        // deserialiser rx and ry (if present) reset x and y
+        let r = non_default(&key.rotation, &self.r);
+        let (rx, ry) = if key.rx != self.rx || key.ry != self.ry {
+            self.x = key.rx;
+            self.y = key.ry;
+            (
+                non_default(&key.rx, &self.rx),
+                non_default(&key.ry, &self.ry),
+            )
+        } else {
+            (None, None)
+        };
+        self.r = key.rotation;
+        self.rx = key.rx;
+        self.ry = key.ry;
+
+        let y = (key.y != self.y).then(|| key.y - self.y);
+        let x = (key.x != self.x).then(|| key.x - self.x);
+        self.y = key.y;
+        self.x = key.x + key.width.max(key.x2 + key.width2);
+
+        let c = non_default(&key.color, &self.c);
+        self.c = key.color;
+
+        let (alignment, legends) = best_alignment(key, self.a);
+        let t = self.text_colors(&legends);
+
+        let g = non_default(&key.ghosted, &self.g);
+        self.g = key.ghosted;
+        let p = non_default(&key.profile, &self.p);
+        self.p.clone_from(&key.profile);
+        let sm = non_default(&key.key_switch.mount, &self.sm);
+        self.sm.clone_from(&key.key_switch.mount);
+        let sb = non_default(&key.key_switch.brand, &self.sb);
+        self.sb.clone_from(&key.key_switch.brand);
+        let st = non_default(&key.key_switch.typ, &self.st);
+        self.st.clone_from(&key.key_switch.typ);
+
+        let a = (alignment != self.a).then(|| usize::from(alignment));
+        self.a = alignment;
+        let (f, f2, fa) = self.font_sizes(&legends);
+
+        let props = KlePropsObject {
+            r,
+            rx,
+            ry,
+            y,
+            x,
+            c,
+            t,
+            g,
+            p,
+            sm,
+            sb,
+            st,
+            a,
+            f,
+            f2,
+            fa,
+            // Per-key properties
+            w: non_default(&key.width, &T::one()),
+            h: non_default(&key.height, &T::one()),
+            w2: non_default(&key.width2, &key.width),
+            h2: non_default(&key.height2, &key.height),
+            x2: non_default(&key.x2, &T::zero()),
+            y2: non_default(&key.y2, &T::zero()),
+            n: key.homing.then_some(true),
+            l: key.stepped.then_some(true),
+            d: key.decal.then_some(true),
+        };
+
+        (props, legend_string(&legends))
+    }
+
+    #[inline]
+    fn next_line(&mut self) {
+        self.x = self.rx; // x resets to rx
+        self.y = self.y + T::one();
+    }
+}
+
+const NUM_ALIGNMENTS: usize = 8;
+
+pub(crate) fn serialize_layout<T>(keys: &[Key<T>]) -> Vec<Vec<KleLegendsOrProps<T>>>
+where
+    T: Real,
+{
+    let mut state = KleProps::default();
+    let mut layout = Vec::new();
+    let mut row = Vec::new();
+
+    for key in keys {
+        // Start a new row if the key is on a different line or in a different rotation cluster
+        let row_changed = key.y != state.y;
+        let cluster_changed = key.rotation != state.r || key.rx != state.rx || key.ry != state.ry;
+        if !row.is_empty() && (row_changed || cluster_changed) {
+            layout.push(std::mem::take(&mut row));
+            state.next_line();
+        }
+
+        let (props, legends) = state.serialize_key(key);
+        if !props.is_empty() {
+            row.push(KleLegendsOrProps::Props(Box::new(props)));
+        }
+        row.push(KleLegendsOrProps::Legend(legends));
+    }
+
+    if !row.is_empty() {
+        layout.push(row);
+    }
+
+    layout
+}
+
+impl<T> From<&Keyboard<T>> for KleKeyboard<T>
+where
+    T: Real,
+{
+    fn from(value: &Keyboard<T>) -> Self {
+        // KLE omits the metadata entirely if it's all default
+        let meta = (value.metadata != Metadata::default()).then(|| (&value.metadata).into());
+
+        Self {
+            meta,
+            layout: serialize_layout(&value.keys),
+        }
+    }
+}
+
+impl<T> Key<T>
+where
+    T: Real,
+{
+    /// Returns the key's legends in KLE's newline delimited format, along with the alignment
+    /// (KLE's `a` property) needed to interpret it.
+    ///
+    /// KLE stores legends in a different order depending on the key's alignment, so this uses the
+    /// alignment that results in the shortest string, preferring KLE's default alignment where
+    /// possible. Deserialising the string with the returned alignment gives the key's legends
+    /// again. Any trailing empty legends are omitted.
+    #[must_use]
+    pub fn legend_string(&self) -> (usize, String) {
+        let (alignment, legends) = best_alignment(self, Alignment::default());
+        (usize::from(alignment), legend_string(&legends))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use isclose::assert_is_close;
+
+    use super::*;
+
+    fn legend(text: &str) -> Legend {
+        Legend {
+            text: text.into(),
+            ..Legend::default()
+        }
+    }
+
+    #[test]
+    fn test_background_from() {
+        let bg = KleBackground::from(&Background::default());
+        assert!(bg.name.is_none());
+        assert!(bg.style.is_none());
+
+        let bg = KleBackground::from(&Background {
+            name: "name".into(),
+            style: "style".into(),
+        });
+        assert_eq!(bg.name.unwrap(), "name");
+        assert_eq!(bg.style.unwrap(), "style");
+    }
+
+    #[test]
+    fn test_metadata_from() {
+        let md = KleMetadata::from(&Metadata::default());
+        assert!(md.backcolor.is_none());
+        assert!(md.background.is_none());
+        assert!(md.name.is_none());
+        assert!(md.pcb.is_none());
+
+        let md = KleMetadata::from(&Metadata {
+            name: "name".into(),
+            pcb_mount: true,
+            background: Background {
+                name: "name".into(),
+                ..Background::default()
+            },
+            ..Metadata::default()
+        });
+        assert_eq!(md.name.unwrap(), "name");
+        assert_eq!(md.pcb, Some(true));
+        assert!(md.background.unwrap().style.is_none());
+        assert!(md.author.is_none());
+    }
+
+    #[test]
+    fn test_legend_string() {
+        let legends = [Some(legend("A")), None, Some(legend("B")), None];
+        let legends: Vec<_> = legends.iter().map(Option::as_ref).collect();
+        assert_eq!(legend_string(&legends), "A\n\nB");
+
+        assert_eq!(legend_string(&[None, None]), "");
+    }
+
+    #[test]
+    fn test_key_legend_string() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[["A\nB\nC\nD\nE", "\n\n\n\n\n\n\n\n\nD", {"a": 7}, "E", ""]]"#,
+        )
+        .unwrap();
+        let strings: Vec<_> = kb.keys.iter().map(Key::legend_string).collect();
+        assert_eq!(
+            strings,
+            [
+                (4, "A\nB\nC\nD\nE".into()),
+                (3, "D".into()),
+                (3, "E".into()),
+                (4, String::new()),
+            ]
+        );
+
+        // Every key round trips through its legend string and alignment
+        for key in &kb.keys {
+            let (alignment, legends) = key.legend_string();
+            let json = format!(
+                r#"[[{{"a": {alignment}}}, {}]]"#,
+                serde_json::to_string(&legends).unwrap()
+            );
+            let round_trip: Keyboard = serde_json::from_str(&json).unwrap();
+            assert_eq!(round_trip.keys[0].legends, key.legends);
+        }
+    }
+
+    #[test]
+    fn test_kle_props_serialize_key() {
+        let mut state = <KleProps>::default();
+        let mut key = Key::default();
+        key.legends[0] = Some(legend("A"));
+        let (props, legends) = state.serialize_key(&key);
+        assert!(props.is_empty());
+        assert_eq!(legends, "A");
+        assert_is_close!(state.x, 1.0);
+
+        let mut key = Key {
+            x: 2.0,
+            width: 2.0,
+            width2: 2.0,
+            color: Color::new(255, 0, 0, 255),
+            decal: true,
+            ..Key::default()
+        };
+        key.legends[2] = Some(Legend {
+            text: "B".into(),
+            size: 5,
+            color: Color::new(0, 0, 255, 255),
+        });
+        let (props, legends) = state.serialize_key(&key);
+        assert_is_close!(props.x.unwrap(), 1.0);
+        assert_is_close!(props.w.unwrap(), 2.0);
+        assert!(props.w2.is_none());
+        assert_eq!(props.c, Some(Color::new(255, 0, 0, 255)));
+        assert_eq!(props.t, Some(vec![Some(Color::new(0, 0, 255, 255))]));
+        assert_eq!(props.f, Some(5));
+        assert!(props.a.is_none());
+        assert_eq!(props.d, Some(true));
+        assert_eq!(legends, "\n\nB");
+        assert_is_close!(state.x, 4.0);
+    }
+
+    #[test]
+    fn test_serialize_layout() {
+        let kb: Keyboard = serde_json::from_str(
+            r##"[
+                [{"a": 7, "f": 4}, "A", "B", {"x": 0.5, "w": 1.5}, "C"],
+                [{"y": 0.5, "c": "#ff0000"}, "D"],
+                [{"r": 15, "rx": 1, "ry": 2}, "E"]
+            ]"##,
+        )
+        .unwrap();
+        let layout = serialize_layout(&kb.keys);
+
+        assert_eq!(layout.len(), 3);
+        assert_eq!(layout[0].len(), 5);
+        assert_eq!(layout[1].len(), 2);
+        assert_eq!(layout[2].len(), 2);
+    }
+
+    #[test]
+    fn test_serialize_decal_round_trip() {
+        let kb: Keyboard = serde_json::from_str(
+            r#"[
+                ["A", {"d": true, "x": 0.5, "w": 2}, "Decal", "B"],
+                [{"d": true}, "Decal\nRow", {"d": true}, "Another"],
+                ["C"]
+            ]"#,
+        )
+        .unwrap();
+        let decals: Vec<_> = kb.keys.iter().map(|key| key.decal).collect();
+        assert_eq!(decals, [false, true, false, true, true, false]);
+
+        // Decals keep their position and legends, and d is only emitted for each decal since the
+        // deserialiser resets it after every key
+        let json = serde_json::to_string(&kb).unwrap();
+        assert_eq!(
+            json,
+            r#"[["A",{"x":0.5,"w":2,"d":true},"Decal","B"],[{"d":true},"Decal\nRow",{"d":true},"Another"],["C"]]"#
+        );
+
+        let result: Keyboard = serde_json::from_str(&json).unwrap();
+        assert_eq!(result, kb);
+    }
+}
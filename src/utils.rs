@@ -1,5 +1,6 @@
-use std::{fmt::Debug, iter};
+use std::fmt::Debug;
 
+use num_traits::real::Real;
 use serde::{
     de::{Error, Unexpected},
     Deserialize,
@@ -10,6 +11,24 @@ use crate::{Legend, NUM_LEGENDS};
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct BoundsError;
 
+// Converts a numeric constant to T. Every sensible Real type can represent the small constants
+// we use, so this should never fail in practice
+pub(crate) fn real<T>(value: f64) -> T
+where
+    T: Real,
+{
+    T::from(value).unwrap_or_else(|| unreachable!("{value} cannot be represented"))
+}
+
+// Converts a value to f64 for output. This only fails for values outside of f64's range, which
+// cannot occur in any sensible layout, so fall back to zero
+pub(crate) fn to_f64<T>(value: T) -> f64
+where
+    T: Real,
+{
+    value.to_f64().unwrap_or_default()
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub(crate) struct BoundedUsize<const MAX: usize, const DEF: usize>(usize);
 
@@ -77,24 +96,35 @@ const LEGEND_MAPPING: [[usize; NUM_LEGENDS]; 8] = [
     [4, 0, 1, 2, 10, 3, 5, 6, 7, 8, 9, 11], // 7 = center front & x & y
 ];
 
-pub(crate) fn realign_legends<T>(values: T, alignment: Alignment) -> Vec<Option<Legend>>
+pub(crate) fn realign_legends<T>(values: T, alignment: Alignment) -> [Option<Legend>; NUM_LEGENDS]
 where
     T: IntoIterator<Item = Option<Legend>>,
 {
     // Guaranteed to be in range because of newtype
     let mapping = LEGEND_MAPPING[usize::from(alignment)];
 
-    let values = values.into_iter().chain(iter::repeat(None));
-    let mut sorted = mapping.iter().zip(values).collect::<Vec<_>>();
-    sorted.sort_by_key(|el| el.0);
+    let mut result: [Option<Legend>; NUM_LEGENDS] = Default::default();
+    for (&i, value) in mapping.iter().zip(values) {
+        result[i] = value;
+    }
+    result
+}
+
+// The inverse of realign_legends, returns the legends in the order KLE expects them for the given
+// alignment
+pub(crate) fn unalign_legends(
+    legends: &[Option<Legend>; NUM_LEGENDS],
+    alignment: Alignment,
+) -> [Option<&Legend>; NUM_LEGENDS] {
+    let mapping = LEGEND_MAPPING[usize::from(alignment)];
 
-    let values = sorted.into_iter().map(|el| el.1);
-    //std::array::from_fn(|_| values.next().unwrap_or(None))
-    values.collect()
+    std::array::from_fn(|i| legends[mapping[i]].as_ref())
 }
 
 #[cfg(test)]
 mod tests {
+    use isclose::assert_is_close;
+
     use super::*;
 
     use serde::de::{
@@ -102,6 +132,12 @@ mod tests {
         IntoDeserializer,
     };
 
+    #[test]
+    fn test_real() {
+        assert_is_close!(real::<f64>(90.0), 90.0);
+        assert_is_close!(real::<f32>(0.5), 0.5);
+    }
+
     #[test]
     fn test_bounded_usize_new() {
         let value = BoundedUsize::<10, 5>::new(7);
@@ -173,4 +209,30 @@ mod tests {
 
         assert_eq!(result_text, expected);
     }
+
+    #[test]
+    fn test_unalign_legends() {
+        let legends = ["A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L"].map(|text| {
+            Some(Legend {
+                text: text.into(),
+                ..Legend::default()
+            })
+        });
+
+        for alignment in 0..=MAX_ALIGNMENT {
+            let alignment = Alignment::new(alignment).unwrap();
+            let realigned = realign_legends(legends.clone(), alignment);
+            let result = unalign_legends(&realigned, alignment);
+            let result_text: Vec<_> = result.iter().map(|l| l.unwrap().text.as_str()).collect();
+
+            assert_eq!(
+                result_text,
+                ["A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L"]
+            );
+        }
+
+        let legends = Default::default();
+        let result = unalign_legends(&legends, Alignment::default());
+        assert!(result.iter().all(Option::is_none));
+    }
 }
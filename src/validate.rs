@@ -0,0 +1,291 @@
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+/// An error found while validating the structure of a KLE JSON file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    /// The location of the error within the JSON, for example `[1][3].w`. An empty path refers to
+    /// the root value.
+    pub path: String,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl SchemaError {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Number,
+    Bool,
+    String,
+    Color,
+    Colors,
+    Alignment,
+    FontSize,
+    FontSizes,
+    Background,
+}
+
+const METADATA_PROPS: [(&str, Kind); 12] = [
+    ("author", Kind::String),
+    ("backcolor", Kind::Color),
+    ("background", Kind::Background),
+    ("name", Kind::String),
+    ("notes", Kind::String),
+    ("radii", Kind::String),
+    ("switchMount", Kind::String),
+    ("switchBrand", Kind::String),
+    ("switchType", Kind::String),
+    ("css", Kind::String),
+    ("pcb", Kind::Bool),
+    ("plate", Kind::Bool),
+];
+
+const KEY_PROPS: [(&str, Kind); 25] = [
+    ("x", Kind::Number),
+    ("y", Kind::Number),
+    ("w", Kind::Number),
+    ("h", Kind::Number),
+    ("x2", Kind::Number),
+    ("y2", Kind::Number),
+    ("w2", Kind::Number),
+    ("h2", Kind::Number),
+    ("r", Kind::Number),
+    ("rx", Kind::Number),
+    ("ry", Kind::Number),
+    ("l", Kind::Bool),
+    ("n", Kind::Bool),
+    ("d", Kind::Bool),
+    ("g", Kind::Bool),
+    ("sm", Kind::String),
+    ("sb", Kind::String),
+    ("st", Kind::String),
+    ("c", Kind::Color),
+    ("t", Kind::Colors),
+    ("a", Kind::Alignment),
+    ("p", Kind::String),
+    ("f", Kind::FontSize),
+    ("f2", Kind::FontSize),
+    ("fa", Kind::FontSizes),
+];
+
+fn is_color(value: &str) -> bool {
+    csscolorparser::parse(value).is_ok()
+}
+
+fn is_bounded(value: &Value, max: u64) -> bool {
+    value.as_u64().map_or(false, |v| v <= max)
+}
+
+fn check_value(path: &str, kind: Kind, value: &Value, errors: &mut Vec<SchemaError>) {
+    let expected = match kind {
+        Kind::Number if value.is_number() => return,
+        Kind::Number => "a number",
+        Kind::Bool if value.is_boolean() => return,
+        Kind::Bool => "a boolean",
+        Kind::String if value.is_string() => return,
+        Kind::String => "a string",
+        Kind::Color if value.as_str().map_or(false, is_color) => return,
+        Kind::Color => "a CSS color value",
+        Kind::Colors
+            if value
+                .as_str()
+                .map_or(false, |s| s.lines().all(|c| c.is_empty() || is_color(c))) =>
+        {
+            return
+        }
+        Kind::Colors => "a newline delimited string of CSS color values",
+        Kind::Alignment if is_bounded(value, 7) => return,
+        Kind::Alignment => "an integer 0 <= x <= 7",
+        // Out of range font sizes are clamped when deserialising, so any size is accepted here
+        Kind::FontSize if value.is_u64() => return,
+        Kind::FontSize => "a non-negative integer",
+        Kind::FontSizes => {
+            if let Some(array) = value.as_array() {
+                for (i, value) in array.iter().enumerate() {
+                    check_value(&format!("{path}[{i}]"), Kind::FontSize, value, errors);
+                }
+                return;
+            }
+            "an array of non-negative integers"
+        }
+        Kind::Background => {
+            if let Some(object) = value.as_object() {
+                let props = [("name", Kind::String), ("style", Kind::String)];
+                check_object(path, object, &props, errors);
+                return;
+            }
+            "an object"
+        }
+    };
+
+    errors.push(SchemaError::new(path, format!("expected {expected}")));
+}
+
+fn check_object(
+    path: &str,
+    object: &Map<String, Value>,
+    props: &[(&str, Kind)],
+    errors: &mut Vec<SchemaError>,
+) {
+    // Unknown properties are ignored by KLE (and when deserialising), so we don't flag them
+    for &(name, kind) in props {
+        if let Some(value) = object.get(name) {
+            check_value(&format!("{path}.{name}"), kind, value, errors);
+        }
+    }
+}
+
+fn check_row(path: &str, row: &[Value], errors: &mut Vec<SchemaError>) {
+    for (i, value) in row.iter().enumerate() {
+        let path = format!("{path}[{i}]");
+        match value {
+            Value::String(_) => {}
+            Value::Object(object) => check_object(&path, object, &KEY_PROPS, errors),
+            _ => errors.push(SchemaError::new(
+                path,
+                "expected a legend string or a property object",
+            )),
+        }
+    }
+}
+
+/// Validates the structure of a KLE JSON file without deserialising it.
+///
+/// This checks the input is an array containing an optional leading metadata object followed by
+/// rows, where each row is an array of legend strings and property objects. Known metadata and
+/// key properties are checked to have the correct type. Unknown properties are ignored, matching
+/// the behaviour of KLE.
+///
+/// Unlike deserialisation, validation does not stop at the first problem, making it suitable for
+/// linting KLE JSON in an editor.
+///
+/// # Errors
+///
+/// Returns all errors found in the input. If the input is not valid JSON, a single error is
+/// returned.
+pub fn validate_json(json: &str) -> Result<(), Vec<SchemaError>> {
+    let value: Value = serde_json::from_str(json)
+        .map_err(|err| vec![SchemaError::new("", format!("invalid JSON: {err}"))])?;
+
+    let array = value
+        .as_array()
+        .ok_or_else(|| vec![SchemaError::new("", "expected an array")])?;
+
+    let mut errors = Vec::new();
+    for (i, value) in array.iter().enumerate() {
+        let path = format!("[{i}]");
+        match value {
+            Value::Array(row) => check_row(&path, row, &mut errors),
+            Value::Object(meta) if i == 0 => {
+                check_object(&path, meta, &METADATA_PROPS, &mut errors);
+            }
+            Value::Object(_) => errors.push(SchemaError::new(
+                path,
+                "the metadata object must be the first element",
+            )),
+            _ => errors.push(SchemaError::new(path, "expected an array or an object")),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_error_display() {
+        assert_eq!(SchemaError::new("", "message").to_string(), "message");
+        assert_eq!(
+            SchemaError::new("[0].w", "message").to_string(),
+            "[0].w: message"
+        );
+    }
+
+    #[test]
+    fn test_validate_json() {
+        assert_eq!(
+            validate_json(
+                r##"[
+                    {"name": "test", "background": {"name": "bg"}, "unknown": 1},
+                    [{"w": 2, "c": "#f00", "t": "#000\n\n#fff", "a": 7, "fa": [1, 9]}, "A"],
+                    ["B"]
+                ]"##
+            ),
+            Ok(())
+        );
+        assert_eq!(validate_json("[]"), Ok(()));
+
+        let errors = validate_json(
+            r#"[
+                {"name": 1, "pcb": "yes"},
+                [{"w": "2", "c": "notacolor", "a": 9, "fa": [1, -1]}, 3],
+                {"name": "later"},
+                null
+            ]"#,
+        )
+        .unwrap_err();
+        let paths: Vec<_> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "[0].name",
+                "[0].pcb",
+                "[1][0].w",
+                "[1][0].c",
+                "[1][0].a",
+                "[1][0].fa[1]",
+                "[1][1]",
+                "[2]",
+                "[3]"
+            ]
+        );
+
+        let errors = validate_json(r#"{"name": "test"}"#).unwrap_err();
+        assert_eq!(errors, [SchemaError::new("", "expected an array")]);
+
+        let errors = validate_json("[").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.starts_with("invalid JSON"));
+    }
+
+    #[test]
+    fn test_validate_json_font_sizes() {
+        // Font sizes outside of KLE's range are accepted since they are clamped when deserialising
+        assert_eq!(
+            validate_json(r#"[[{"f": 12, "f2": 0, "fa": [10, 3]}, "A"]]"#),
+            Ok(())
+        );
+
+        let errors = validate_json(r#"[[{"f": -1, "f2": 1.5, "fa": [1, "2"]}, "A"]]"#).unwrap_err();
+        let paths: Vec<_> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["[0][0].f", "[0][0].f2", "[0][0].fa[1]"]);
+        assert_eq!(errors[0].message, "expected a non-negative integer");
+    }
+}